@@ -0,0 +1,106 @@
+use std::sync::Arc;
+use num_complex::Complex;
+use rustfft::{Fft, FftNum, FftPlanner, num_traits::{Float, Zero}};
+
+use crate::{ConvolutionError, FFTConvolution, Filter};
+use crate::dtype::RingBuffer;
+
+/// Overlap-add convolution of a complex signal with a complex IR
+///
+/// This works like `FFTConvolution`, with the same latency and chunking, but keeps
+///  both parts of the inverse transform, as needed for analytic signals, frequency
+///  shifters and other single-sideband effects. For a real IR and a real input the
+///  real part of the output is that of `FFTConvolution` and the imaginary part is
+///  zero, up to rounding. It always plans complex FFTs, even with the `realfft`
+///  feature.
+pub struct ComplexFFTConvolution<T = f64> where T: FftNum + Float {
+    x: RingBuffer<Complex<T>>,
+    out: RingBuffer<Complex<T>>,
+    window_size: usize,
+    ir_len: usize,
+    ir_fft_cache: Vec<Complex<T>>,
+    forward: Arc<dyn Fft<T>>,
+    inverse: Arc<dyn Fft<T>>,
+    fft_buffer: Vec<Complex<T>>,
+    scratch: Vec<Complex<T>>,
+}
+impl<T> ComplexFFTConvolution<T> where T: FftNum + Float {
+    /// Creates the filter, panicking where `try_new` would return an error
+    pub fn new(ir: Vec<Complex<T>>, window_size: usize) -> ComplexFFTConvolution<T> {
+        Self::try_new(ir, window_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking the IR and window size as
+    ///  `FFTConvolution::try_new` does
+    pub fn try_new(ir: Vec<Complex<T>>, window_size: usize) -> Result<ComplexFFTConvolution<T>, ConvolutionError> {
+        let padded_window_size = FFTConvolution::<T>::checked_padded_window_size(ir.len(), window_size)?;
+        let mut planner = FftPlanner::new();
+        let forward = planner.plan_fft_forward(padded_window_size);
+        let inverse = planner.plan_fft_inverse(padded_window_size);
+        let scratch_len = forward.get_inplace_scratch_len().max(inverse.get_inplace_scratch_len());
+        let mut scratch = vec![Complex::zero(); scratch_len];
+        let mut ir_fft_cache = vec![Complex::zero(); padded_window_size];
+        ir_fft_cache[..ir.len()].copy_from_slice(&ir);
+        forward.process_with_scratch(&mut ir_fft_cache, &mut scratch);
+        Ok(ComplexFFTConvolution {
+            x: RingBuffer::new(window_size),
+            out: RingBuffer::new(padded_window_size).initialize(Complex::zero()),
+            window_size,
+            ir_len: ir.len(),
+            ir_fft_cache,
+            forward,
+            inverse,
+            fft_buffer: vec![Complex::zero(); padded_window_size],
+            scratch,
+        })
+    }
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+    pub fn ir_len(&self) -> usize {
+        self.ir_len
+    }
+    /// The delay in samples between a sample entering `compute` and its
+    ///  contribution first appearing in the output
+    pub fn latency(&self) -> usize {
+        self.window_size
+    }
+    fn convolve_chunk(&mut self) {
+        let chunk_len = self.x.len();
+        self.x.copy_to_slice(&mut self.fft_buffer);
+        self.fft_buffer[chunk_len..].fill(Complex::zero());
+        self.x.clear();
+        self.forward.process_with_scratch(&mut self.fft_buffer, &mut self.scratch);
+        for (val, ir_val) in self.fft_buffer.iter_mut().zip(self.ir_fft_cache.iter()) {
+            *val = *val * *ir_val;
+        }
+        self.inverse.process_with_scratch(&mut self.fft_buffer, &mut self.scratch);
+        let scale = T::one() / T::from_usize(self.fft_buffer.len()).unwrap();
+        for (out_ref, buf_val) in self.out.inner_mut().iter_mut().zip(self.fft_buffer.iter()) {
+            *out_ref = *out_ref + *buf_val * scale;
+        }
+    }
+}
+impl<T> Filter<Complex<T>> for ComplexFFTConvolution<T> where T: FftNum + Float {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.out.clear();
+        self.out.initialize_again(Complex::zero());
+    }
+    fn compute(&mut self, signal: Complex<T>) -> Complex<T> {
+        let buffered_signal = self.out.pop_front().unwrap();
+        self.out.push_back(Complex::zero());
+
+        self.x.push_back(signal);
+        if self.x.len() == self.window_size {
+            self.convolve_chunk();
+        }
+
+        buffered_signal
+    }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+    fn flush(&mut self) -> Vec<Complex<T>> {
+        (0..self.latency() + self.ir_len() - 1).map(|_| self.compute(Complex::zero())).collect()
+    }
+}
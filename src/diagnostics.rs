@@ -0,0 +1,44 @@
+/// Uniform white noise in `-1.0..1.0` from a xorshift64 generator, so the same
+///  `seed` always gives the same signal on every platform
+pub fn white_noise(len: usize, seed: u64) -> Vec<f64> {
+    // xorshift gets stuck on a zero state
+    let mut state = if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed };
+    (0..len).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        (state >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }).collect()
+}
+
+/// The textbook `O(n * m)` linear convolution of `a` and `b`, of length
+///  `a.len() + b.len() - 1`, or empty if either input is
+pub fn naive_convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut output = vec![0.0; a.len() + b.len() - 1];
+    for (i, a_val) in a.iter().enumerate() {
+        for (j, b_val) in b.iter().enumerate() {
+            output[i + j] += a_val * b_val;
+        }
+    }
+    output
+}
+
+/// The maximum absolute error between `convolve` with an `FFTConvolution` of
+///  `window_size`, flush included, and `naive_convolve`, for `test_len` samples of
+///  `white_noise(test_len, seed)` convolved with `ir`
+///
+/// This checks that a given IR and window size are numerically sound. In `f64`
+///  the error grows with the IR's length and magnitude: for an IR peaking at 1.0
+///  it is around 1e-15 for a few dozen taps and 1e-12 for several seconds at
+///  48 kHz, so anything above about 1e-10 points to a problem. Panics if `ir` is
+///  empty or `window_size` is 0.
+pub fn verify_against_naive(ir: &[f64], window_size: usize, test_len: usize, seed: u64) -> f64 {
+    assert!(!ir.is_empty() && window_size > 0, "verify_against_naive needs a non-empty IR and a window size greater than 0");
+    let signal = white_noise(test_len, seed);
+    let fast = crate::convolve(&signal, ir, window_size);
+    let naive = naive_convolve(&signal, ir);
+    fast.iter().zip(naive.iter()).fold(0.0, |max, (fast_val, naive_val)| max.max((fast_val - naive_val).abs()))
+}
@@ -10,6 +10,13 @@ use slice_ring_buffer::SliceRingBuffer;
 #[cfg(feature="slice-ring-buffer")]
 pub type BaseDequeImplementation<T> = SliceRingBuffer<T>;
 
+/// Front-to-back iterator over the elements of a `RingBuffer`
+#[cfg(not(feature="slice-ring-buffer"))]
+pub type Iter<'a, T> = std::collections::vec_deque::Iter<'a, T>;
+/// Front-to-back iterator over the elements of a `RingBuffer`
+#[cfg(feature="slice-ring-buffer")]
+pub type Iter<'a, T> = std::slice::Iter<'a, T>;
+
 #[derive(Debug, Clone)]
 pub struct RingBuffer<T> {
     inner: BaseDequeImplementation<T>,
@@ -19,7 +26,7 @@ pub struct RingBuffer<T> {
 
 impl<T> From<Vec<T>> for RingBuffer<T> where T: Clone {
     fn from(value: Vec<T>) -> Self {
-        Self::from(BaseDequeImplementation::from_iter(value.into_iter()))
+        Self::from(BaseDequeImplementation::from_iter(value))
     }
 }
 impl<T> From<BaseDequeImplementation<T>> for RingBuffer<T> where T: Clone {
@@ -27,8 +34,41 @@ impl<T> From<BaseDequeImplementation<T>> for RingBuffer<T> where T: Clone {
         Self::from_deque(value)
     }
 }
+impl<T> From<RingBuffer<T>> for Vec<T> where T: Clone {
+    /// Collects the elements from the front (oldest) to the back (newest)
+    fn from(value: RingBuffer<T>) -> Self {
+        value.inner.into_iter().collect()
+    }
+}
+
+impl<T> FromIterator<T> for RingBuffer<T> where T: Clone {
+    /// Collects into a full buffer whose capacity is the number of items
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from(iter.into_iter().collect::<Vec<T>>())
+    }
+}
+impl<T> Extend<T> for RingBuffer<T> where T: Clone {
+    /// Pushes every item onto the back, evicting from the front once full
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.push_back(item);
+        }
+    }
+}
+impl<'a, T> IntoIterator for &'a RingBuffer<T> where T: Clone {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
 
 impl<T> RingBuffer<T> where T: Clone {
+    /// Creates an empty buffer that holds up to `capacity` elements
+    ///
+    /// A buffer of capacity 0 is valid but inert: every push is a no-op, and
+    ///  `front`, `back` and the pops always return `None`. Use `try_new` to reject a
+    ///  computed capacity of 0 instead.
     pub fn new(capacity: usize) -> Self {
         Self {
             inner: BaseDequeImplementation::with_capacity(capacity),
@@ -36,6 +76,14 @@ impl<T> RingBuffer<T> where T: Clone {
             length: 0
         }
     }
+    /// Same as `new`, but returns `None` for a capacity of 0
+    pub fn try_new(capacity: usize) -> Option<Self> {
+        if capacity == 0 {
+            None
+        } else {
+            Some(Self::new(capacity))
+        }
+    }
     pub fn from_deque(deque: BaseDequeImplementation<T>) -> RingBuffer<T> {
         Self {
             capacity: deque.len(),
@@ -52,9 +100,19 @@ impl<T> RingBuffer<T> where T: Clone {
     pub fn capacity(&self) -> usize {
         self.capacity
     }
+    /// Whether the buffer holds `capacity()` elements, so that the next push
+    ///  evicts one; a buffer of capacity 0 is always full
+    pub fn is_full(&self) -> bool {
+        self.length == self.capacity
+    }
     pub fn into_deque(self) -> BaseDequeImplementation<T> {
         self.inner
     }
+    /// Copies the elements into a `Vec`, from the front (oldest) to the back
+    ///  (newest)
+    pub fn to_vec(&self) -> Vec<T> {
+        self.inner.iter().cloned().collect()
+    }
     #[cfg(not(feature="slice-ring-buffer"))]
     pub fn inner(&self) -> &BaseDequeImplementation<T> {
         &self.inner
@@ -78,25 +136,120 @@ impl<T> RingBuffer<T> where T: Clone {
     pub fn inner_mut(&mut self) -> &mut [T] {
         &mut self.inner
     }
+    /// The contents in logical order as two slices, the first of which is the
+    ///  front portion, mirroring `VecDeque::as_slices`
+    ///
+    /// The contents may be split wherever the backing deque wraps around; with
+    ///  the `slice-ring-buffer` backend they are always contiguous, so the second
+    ///  slice is empty.
+    #[cfg(not(feature="slice-ring-buffer"))]
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.inner.as_slices()
+    }
+    #[cfg(feature="slice-ring-buffer")]
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        (&self.inner, &[])
+    }
+    /// Copies the elements from front to back into the start of `target`, with at
+    ///  most two `copy_from_slice` calls, one per slice of `as_slices`
+    ///
+    /// Panics if `target` is shorter than `len()`.
+    pub fn copy_to_slice(&self, target: &mut [T]) where T: Copy {
+        let (first, second) = self.as_slices();
+        target[..first.len()].copy_from_slice(first);
+        target[first.len()..first.len() + second.len()].copy_from_slice(second);
+    }
+    /// Same as `as_slices`, but mutable, mirroring `VecDeque::as_mut_slices`
+    #[cfg(not(feature="slice-ring-buffer"))]
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        self.inner.as_mut_slices()
+    }
+    #[cfg(feature="slice-ring-buffer")]
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
+        (&mut self.inner, &mut [])
+    }
+    /// Swaps the elements `i` and `j` places from the front
+    ///
+    /// Panics if either index is out of bounds.
+    pub fn swap(&mut self, i: usize, j: usize) {
+        self.check_invariant();
+        assert!(i < self.length && j < self.length, "index out of bounds");
+        self.inner.swap(i, j);
+    }
+    /// Applies `f` to every element from front to back, keeping the capacity and
+    ///  length
+    pub fn map<U, F>(self, mut f: F) -> RingBuffer<U> where F: FnMut(T) -> U, U: Clone {
+        let mut inner = BaseDequeImplementation::with_capacity(self.capacity);
+        for item in self.inner {
+            inner.push_back(f(item));
+        }
+        RingBuffer {
+            inner,
+            capacity: self.capacity,
+            length: self.length,
+        }
+    }
+    /// Asserts that the length matches the backing deque and fits within the
+    ///  capacity
+    ///
+    /// This is only checked in debug builds, where every mutating method calls it
+    ///  on entry, and again before returning if it changed the length, so that a
+    ///  length desynced through `inner_mut` is caught at the next such call rather
+    ///  than far from the cause. In release builds it does nothing.
+    ///
+    /// Note
+    /// ====
+    /// `drain` and `empty`, and `pop_front_n` and `pop_back_n` which go through
+    ///  `drain`, are the exception: they recompute the length from the inner deque
+    ///  instead, and are the way to resync it after changing the deque directly.
+    pub fn check_invariant(&self) {
+        debug_assert_eq!(self.length, self.inner.len(), "the length of the RingBuffer is out of sync with its inner deque");
+        debug_assert!(self.length <= self.capacity, "the length of the RingBuffer exceeds its capacity");
+    }
+    /// Iterates from the front (oldest) to the back (newest) element
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
+    }
     pub fn clear(&mut self) {
+        self.check_invariant();
         self.inner.clear();
         self.length = 0;
+        self.check_invariant();
     }
     pub fn drain<R>(&mut self, range: R) -> Vec<T>
     where
         R: RangeBounds<usize> {
         let drain: Vec<T> = self.inner.drain(range).collect();
-        self.length -= drain.len();
+        // Recomputed rather than subtracted so a length desynced through
+        //  `inner_mut` can't underflow
+        self.length = self.inner.len();
+        self.check_invariant();
         drain
     }
     pub fn empty(&mut self) -> Vec<T> {
-        self.length = 0;
-        self.inner.drain(..).collect()
+        let drain: Vec<T> = self.inner.drain(..).collect();
+        self.length = self.inner.len();
+        self.check_invariant();
+        drain
     }
+    /// Overwrites every element currently in the buffer with `value`, keeping the
+    ///  length and capacity
+    pub fn fill(&mut self, value: T) {
+        self.check_invariant();
+        for item in self.inner.iter_mut() {
+            *item = value.clone();
+        }
+    }
+    /// Fills the remainder of the buffer up to its capacity by pushing `value` onto
+    ///  the back, keeping the elements already present at the front
+    ///
+    /// Call `clear` first to reset the whole buffer to `value`.
     pub fn initialize_again(&mut self, value: T) {
-        for _ in 0..self.capacity {
+        self.check_invariant();
+        for _ in self.length..self.capacity {
             self.push_back(value.clone());
         }
+        self.check_invariant();
     }
     pub fn initialize(mut self, value: T) -> Self {
         self.initialize_again(value);
@@ -111,8 +264,8 @@ impl<T> RingBuffer<T> where T: Clone {
         self.inner.back_mut()
     }
     pub fn back_n(&self, n: usize) -> Option<&T> {
-        if self.capacity == 0 || n >= self.inner.len() { return None; }
-        self.inner.get(self.inner.len()-n-1)
+        if self.capacity == 0 || n >= self.length { return None; }
+        self.inner.get(self.length-n-1)
     }
     pub fn front(&self) -> Option<&T> {
         if self.capacity == 0 { return None; }
@@ -123,90 +276,254 @@ impl<T> RingBuffer<T> where T: Clone {
         self.inner.front_mut()
     }
     pub fn front_n(&self, n: usize) -> Option<&T> {
-        if self.capacity == 0 || n >= self.inner.len() { return None; }
+        if self.capacity == 0 || n >= self.length { return None; }
         self.inner.get(n)
     }
+    /// The element `index` places from the front, so that `get(0)` is the oldest
+    ///  element and `get(len() - 1)` is the newest
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.length { return None; }
+        self.inner.get(index)
+    }
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.length { return None; }
+        self.inner.get_mut(index)
+    }
     pub fn pop_front(&mut self) -> Option<T> {
+        self.check_invariant();
         if self.capacity == 0 { return None; }
         let item = self.inner.pop_front();
-        if let Some(_) = item {
+        if item.is_some() {
             self.length -= 1;
         }
+        self.check_invariant();
         item
     }
     pub fn pop_back(&mut self) -> Option<T> {
+        self.check_invariant();
         if self.capacity == 0 { return None; }
         let item = self.inner.pop_back();
-        if let Some(_) = item {
+        if item.is_some() {
             self.length -= 1;
         }
+        self.check_invariant();
         item
     }
+    /// Removes up to `n` elements from the front, returned in the order they were
+    ///  removed (front first)
+    pub fn pop_front_n(&mut self, n: usize) -> Vec<T> {
+        let n = n.min(self.inner.len());
+        self.drain(..n)
+    }
+    /// Removes up to `n` elements from the back, returned in the order they were
+    ///  removed (back first)
+    pub fn pop_back_n(&mut self, n: usize) -> Vec<T> {
+        let len = self.inner.len();
+        let mut items = self.drain(len - n.min(len)..);
+        items.reverse();
+        items
+    }
+    /// Drops elements from the front until at most `n` are left, keeping the `n`
+    ///  newest
+    pub fn keep_last(&mut self, n: usize) {
+        self.check_invariant();
+        if self.length > n {
+            self.inner.drain(..self.length - n);
+            self.length = n;
+        }
+        self.check_invariant();
+    }
+    /// Drops elements from the back until at most `n` are left, keeping the `n`
+    ///  oldest
+    pub fn keep_first(&mut self, n: usize) {
+        self.check_invariant();
+        if self.length > n {
+            self.inner.drain(n..);
+            self.length = n;
+        }
+        self.check_invariant();
+    }
+    /// Rotates the contents `n` places towards the front, so that the element at
+    ///  index `n % len()` becomes the front
+    pub fn rotate_left(&mut self, n: usize) {
+        self.check_invariant();
+        if self.length == 0 { return; }
+        let n = n % self.length;
+        self.inner.rotate_left(n);
+    }
+    /// Rotates the contents `n` places towards the back, so that the element at
+    ///  index `len() - 1 - n % len()` becomes the back
+    pub fn rotate_right(&mut self, n: usize) {
+        self.check_invariant();
+        if self.length == 0 { return; }
+        let n = n % self.length;
+        self.inner.rotate_right(n);
+    }
     pub fn fill_front(&mut self, item: T) {
+        self.check_invariant();
         while self.length < self.capacity {
             self.push_front(item.clone());
         }
+        self.check_invariant();
     }
     pub fn fill_back(&mut self, item: T) {
+        self.check_invariant();
         while self.length < self.capacity {
             self.push_back(item.clone());
         }
-    }
-    pub fn to_capacity_front(&mut self, capacity: Option<usize>) {
-        if let Some(capacity) = capacity {
+        self.check_invariant();
+    }
+    /// Changes the capacity, keeping the elements in place
+    ///
+    /// Growing reserves room in the backing deque and leaves the length unchanged.
+    ///  Shrinking below the current length drops the oldest elements from the
+    ///  front, as pushing onto a full buffer would.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.check_invariant();
+        if capacity > self.capacity {
+            self.inner.reserve(capacity - self.length);
             self.capacity = capacity;
+        } else {
+            self.to_capacity_front(Some(capacity));
         }
-        while self.length > self.capacity {
-            self.pop_front();
-        }
+        self.check_invariant();
     }
+    /// Sets the capacity if one is given, dropping elements from the front until
+    ///  the length fits
+    pub fn to_capacity_front(&mut self, capacity: Option<usize>) {
+        // Trimmed before the capacity changes, so that the length never exceeds it
+        let capacity = capacity.unwrap_or(self.capacity);
+        self.keep_last(capacity);
+        self.capacity = capacity;
+        self.check_invariant();
+    }
+    /// Sets the capacity if one is given, dropping elements from the back until
+    ///  the length fits
     pub fn to_capacity_back(&mut self, capacity: Option<usize>) {
-        if let Some(capacity) = capacity {
-            self.capacity = capacity;
-        }
-        while self.length > self.capacity {
-            self.pop_back();
-        }
+        let capacity = capacity.unwrap_or(self.capacity);
+        self.keep_first(capacity);
+        self.capacity = capacity;
+        self.check_invariant();
     }
     pub fn push_back(&mut self, item: T) {
+        self.check_invariant();
         if self.capacity == 0 { return; }
 
         // while self.length >= self.capacity {
         //     self.pop_front();
         // }
         self.to_capacity_front(None);
-        if self.length == self.capacity {
+        if self.is_full() {
             self.pop_front();
         }
         
         self.inner.push_back(item);
         self.length += 1;
+        self.check_invariant();
+    }
+    /// Pushes every item of `items` onto the back, evicting from the front as
+    ///  needed, so that only the last `capacity()` items are kept when `items` is
+    ///  longer than that
+    pub fn extend_from_slice(&mut self, items: &[T]) where T: Copy {
+        self.check_invariant();
+        if self.capacity == 0 { return; }
+        let items = &items[items.len().saturating_sub(self.capacity)..];
+        self.to_capacity_front(None);
+        let evicted = (self.length + items.len()).saturating_sub(self.capacity);
+        self.inner.drain(..evicted);
+        self.inner.extend(items.iter().copied());
+        self.length = self.length - evicted + items.len();
+        self.check_invariant();
+    }
+    /// Pushes `item` onto the back and returns the element evicted from the front,
+    ///  which makes a buffer initialized to capacity behave as a fixed delay line
+    ///  of `capacity` samples
+    ///
+    /// With a capacity of 0 `item` is returned immediately.
+    pub(crate) fn shift(&mut self, item: T) -> T {
+        match self.pop_front() {
+            Some(front) => {
+                self.push_back(item);
+                front
+            },
+            None => item,
+        }
     }
     pub fn push_front(&mut self, item: T) {
+        self.check_invariant();
         if self.capacity == 0 { return; }
 
         // while self.length >= self.capacity {
         //     self.pop_back();
         // }
         self.to_capacity_back(None);
-        if self.length == self.capacity {
+        if self.is_full() {
             self.pop_back();
         }
 
         self.inner.push_front(item);
         self.length += 1;
+        self.check_invariant();
+    }
+}
+
+// The serialized form is the capacity and the elements from front to back, so it
+//  does not depend on the backing deque
+#[cfg(feature="serde")]
+mod serde_impl {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error, ser::SerializeStruct};
+    use super::RingBuffer;
+
+    struct Elements<'a, T>(&'a RingBuffer<T>);
+    impl<T> Serialize for Elements<'_, T> where T: Clone + Serialize {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(self.0.iter())
+        }
+    }
+
+    impl<T> Serialize for RingBuffer<T> where T: Clone + Serialize {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("RingBuffer", 2)?;
+            state.serialize_field("capacity", &self.capacity)?;
+            state.serialize_field("elements", &Elements(self))?;
+            state.end()
+        }
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "RingBuffer")]
+    struct RingBufferRepr<T> {
+        capacity: usize,
+        elements: Vec<T>,
+    }
+
+    impl<'de, T> Deserialize<'de> for RingBuffer<T> where T: Clone + Deserialize<'de> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let repr = RingBufferRepr::<T>::deserialize(deserializer)?;
+            if repr.elements.len() > repr.capacity {
+                return Err(D::Error::custom(format!("{} elements do not fit in a capacity of {}", repr.elements.len(), repr.capacity)));
+            }
+            let mut buffer = RingBuffer::new(repr.capacity);
+            buffer.extend(repr.elements);
+            Ok(buffer)
+        }
     }
 }
 
 pub trait ChunkedBuffer<T> where T: Clone {
     fn buffer_back(&mut self, item: T) -> Option<BaseDequeImplementation<T>>;
     fn buffer_front(&mut self, item: T) -> Option<BaseDequeImplementation<T>>;
+    /// Like `buffer_back`, but the full chunk is moved out instead of cloned, and
+    ///  the buffer starts again from a freshly allocated deque
+    fn take_chunk_back(&mut self, item: T) -> Option<BaseDequeImplementation<T>>;
+    /// The current chunk if it is full, without clearing or cloning it
+    fn peek_chunk(&self) -> Option<&BaseDequeImplementation<T>>;
 }
 
 impl<T> ChunkedBuffer<T> for RingBuffer<T> where T: Clone {
     fn buffer_back(&mut self, item: T) -> Option<BaseDequeImplementation<T>> {
         self.push_back(item);
-        if self.length == self.capacity {
+        if self.is_full() {
             let buf = self.inner.clone();
             self.clear();
             Some(buf)
@@ -216,7 +533,7 @@ impl<T> ChunkedBuffer<T> for RingBuffer<T> where T: Clone {
     }
     fn buffer_front(&mut self, item: T) -> Option<BaseDequeImplementation<T>> {
         self.push_front(item);
-        if self.length == self.capacity {
+        if self.is_full() {
             let buf = self.inner.clone();
             self.clear();
             Some(buf)
@@ -224,5 +541,345 @@ impl<T> ChunkedBuffer<T> for RingBuffer<T> where T: Clone {
             None
         }
     }
+    fn take_chunk_back(&mut self, item: T) -> Option<BaseDequeImplementation<T>> {
+        self.check_invariant();
+        self.push_back(item);
+        if self.is_full() {
+            let buf = std::mem::replace(&mut self.inner, BaseDequeImplementation::with_capacity(self.capacity));
+            self.length = 0;
+            self.check_invariant();
+            Some(buf)
+        } else {
+            None
+        }
+    }
+    fn peek_chunk(&self) -> Option<&BaseDequeImplementation<T>> {
+        if self.is_full() {
+            Some(&self.inner)
+        } else {
+            None
+        }
+    }
+}
+
+/// Emits overlapping frames of `frame_size` elements every `hop_size` pushes,
+///  as needed for STFT-style processing
+///
+/// The first frame is emitted once `frame_size` elements have been pushed, and
+///  each following frame starts `hop_size` elements after the previous one, so
+///  consecutive frames share `frame_size - hop_size` elements.
+#[derive(Debug, Clone)]
+pub struct HopBuffer<T> {
+    buffer: RingBuffer<T>,
+    hop_size: usize,
+    since_last_frame: usize,
+}
+
+impl<T> HopBuffer<T> where T: Clone {
+    pub fn new(frame_size: usize, hop_size: usize) -> Self {
+        assert!(hop_size > 0, "hop_size must be greater than 0");
+        Self {
+            buffer: RingBuffer::new(frame_size),
+            hop_size,
+            since_last_frame: 0,
+        }
+    }
+    pub fn frame_size(&self) -> usize {
+        self.buffer.capacity()
+    }
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+    pub fn buffer(&self) -> &RingBuffer<T> {
+        &self.buffer
+    }
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.since_last_frame = 0;
+    }
+    /// Pushes `item` onto the back, returning a copy of the current frame from
+    ///  front to back whenever a new frame is complete
+    pub fn push(&mut self, item: T) -> Option<BaseDequeImplementation<T>> {
+        self.buffer.push_back(item);
+        self.since_last_frame += 1;
+        if self.buffer.is_full() && self.since_last_frame >= self.hop_size {
+            self.since_last_frame = 0;
+            Some(self.buffer.inner.clone())
+        } else {
+            None
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn get_indexes_from_the_front() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(4);
+        assert_eq!(buffer.get(0), None);
+        assert_eq!(buffer.get_mut(0), None);
+        buffer.push_back(1);
+        buffer.push_back(2);
+        assert_eq!(buffer.get(0), Some(&1));
+        assert_eq!(buffer.get(1), Some(&2));
+        assert_eq!(buffer.get(2), None);
+        for item in 3..=6 {
+            buffer.push_back(item);
+        }
+        assert_eq!((0..4).map(|i| *buffer.get(i).unwrap()).collect::<Vec<_>>(), vec![3, 4, 5, 6]);
+        assert_eq!(buffer.get(4), None);
+        assert_eq!(buffer.get(0), buffer.front_n(0));
+        assert_eq!(buffer.get(3), buffer.back_n(0));
+        *buffer.get_mut(1).unwrap() = 40;
+        assert_eq!(buffer.to_vec(), vec![3, 40, 5, 6]);
+    }
+
+    #[test]
+    fn initialize_again_fills_the_remainder() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(5);
+        buffer.push_back(1);
+        buffer.push_back(2);
+        buffer.initialize_again(0);
+        assert_eq!(buffer.to_vec(), vec![1, 2, 0, 0, 0]);
+        buffer.initialize_again(9);
+        assert_eq!(buffer.to_vec(), vec![1, 2, 0, 0, 0]);
+        buffer.clear();
+        buffer.initialize_again(7);
+        assert_eq!(buffer.to_vec(), vec![7; 5]);
+    }
+
+    #[test]
+    #[cfg(not(feature="slice-ring-buffer"))]
+    fn drain_resyncs_a_desynced_length() {
+        let mut buffer: RingBuffer<i32> = (0..4).collect();
+        buffer.inner_mut().push_back(4);
+        assert_eq!(buffer.drain(..2), vec![0, 1]);
+        assert_eq!(buffer.len(), 3);
+        buffer.inner_mut().pop_front();
+        assert_eq!(buffer.empty(), vec![3, 4]);
+        assert_eq!(buffer.len(), 0);
+    }
+
+    #[test]
+    fn hop_buffer_emits_overlapping_frames() {
+        let mut hop: HopBuffer<i32> = HopBuffer::new(8, 4);
+        let frames: Vec<(i32, Vec<i32>)> = (0..20).filter_map(|item| {
+            hop.push(item).map(|frame| (item, frame.iter().copied().collect()))
+        }).collect();
+        assert_eq!(frames, vec![
+            (7, (0..8).collect()),
+            (11, (4..12).collect()),
+            (15, (8..16).collect()),
+            (19, (12..20).collect()),
+        ]);
+    }
+
+    #[test]
+    #[cfg(feature="serde")]
+    fn serde_round_trips_front_to_back() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(4);
+        for item in 1..=6 {
+            buffer.push_back(item);
+        }
+        let json = serde_json::to_string(&buffer).unwrap();
+        assert_eq!(json, r#"{"capacity":4,"elements":[3,4,5,6]}"#);
+        let restored: RingBuffer<i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.capacity(), 4);
+        assert_eq!(restored.to_vec(), buffer.to_vec());
+        assert!(serde_json::from_str::<RingBuffer<i32>>(r#"{"capacity":1,"elements":[1,2]}"#).is_err());
+    }
+
+    #[test]
+    fn pop_n_clamps_to_the_length() {
+        let mut buffer: RingBuffer<i32> = (0..6).collect();
+        assert_eq!(buffer.pop_front_n(0), Vec::<i32>::new());
+        assert_eq!(buffer.pop_back_n(0), Vec::<i32>::new());
+        assert_eq!(buffer.pop_front_n(2), vec![0, 1]);
+        assert_eq!(buffer.pop_back_n(2), vec![5, 4]);
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(buffer.pop_back_n(10), vec![3, 2]);
+        assert_eq!(buffer.len(), 0);
+        assert_eq!(buffer.pop_front_n(3), Vec::<i32>::new());
+        assert_eq!(buffer.pop_back_n(3), Vec::<i32>::new());
+        buffer.push_back(7);
+        assert_eq!(buffer.pop_front_n(10), vec![7]);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn rotate_keeps_the_capacity() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(6);
+        buffer.extend(0..5);
+        buffer.rotate_left(2);
+        assert_eq!(buffer.to_vec(), vec![2, 3, 4, 0, 1]);
+        buffer.rotate_right(8);
+        assert_eq!(buffer.to_vec(), vec![4, 0, 1, 2, 3]);
+        buffer.rotate_left(5);
+        assert_eq!(buffer.to_vec(), vec![4, 0, 1, 2, 3]);
+        assert_eq!((buffer.len(), buffer.capacity()), (5, 6));
+        buffer.push_back(5);
+        buffer.push_back(6);
+        assert_eq!(buffer.to_vec(), vec![0, 1, 2, 3, 5, 6]);
+        let mut empty: RingBuffer<i32> = RingBuffer::new(3);
+        empty.rotate_left(1);
+        empty.rotate_right(1);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn is_full_at_small_capacities() {
+        let mut none: RingBuffer<i32> = RingBuffer::new(0);
+        assert!(none.is_full());
+        none.push_back(1);
+        assert!(none.is_full());
+        assert!(none.is_empty());
+        let mut one: RingBuffer<i32> = RingBuffer::new(1);
+        assert!(!one.is_full());
+        one.push_back(1);
+        assert!(one.is_full());
+        one.push_back(2);
+        assert!(one.is_full());
+        assert_eq!(one.to_vec(), vec![2]);
+        one.pop_front();
+        assert!(!one.is_full());
+    }
+
+    #[test]
+    fn extend_from_slice_keeps_the_last_items() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(4);
+        buffer.push_back(-1);
+        buffer.extend_from_slice(&[0, 1]);
+        assert_eq!(buffer.to_vec(), vec![-1, 0, 1]);
+        buffer.extend_from_slice(&[2, 3]);
+        assert_eq!(buffer.to_vec(), vec![0, 1, 2, 3]);
+        buffer.extend_from_slice(&(10..20).collect::<Vec<_>>());
+        assert_eq!(buffer.to_vec(), vec![16, 17, 18, 19]);
+        assert_eq!(buffer.len(), 4);
+        buffer.extend_from_slice(&[]);
+        assert_eq!(buffer.to_vec(), vec![16, 17, 18, 19]);
+        let mut none: RingBuffer<i32> = RingBuffer::new(0);
+        none.extend_from_slice(&[1, 2, 3]);
+        assert!(none.is_empty());
+    }
+
+    #[test]
+    fn set_capacity_grows_and_shrinks_in_place() {
+        let mut buffer: RingBuffer<i32> = (0..4).collect();
+        buffer.set_capacity(6);
+        assert_eq!((buffer.to_vec(), buffer.capacity()), (vec![0, 1, 2, 3], 6));
+        buffer.extend(4..8);
+        assert_eq!(buffer.to_vec(), vec![2, 3, 4, 5, 6, 7]);
+        buffer.set_capacity(6);
+        assert_eq!((buffer.to_vec(), buffer.capacity()), (vec![2, 3, 4, 5, 6, 7], 6));
+        buffer.set_capacity(3);
+        assert_eq!((buffer.to_vec(), buffer.capacity()), (vec![5, 6, 7], 3));
+        buffer.push_back(8);
+        assert_eq!(buffer.to_vec(), vec![6, 7, 8]);
+        buffer.set_capacity(0);
+        assert!(buffer.is_empty());
+        assert!(buffer.is_full());
+    }
+
+    #[test]
+    fn back_n_and_front_n_at_full_capacity() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(4);
+        buffer.extend(0..6);
+        assert!(buffer.is_full());
+        assert_eq!((0..4).map(|n| *buffer.front_n(n).unwrap()).collect::<Vec<_>>(), vec![2, 3, 4, 5]);
+        assert_eq!((0..4).map(|n| *buffer.back_n(n).unwrap()).collect::<Vec<_>>(), vec![5, 4, 3, 2]);
+        assert_eq!(buffer.front_n(4), None);
+        assert_eq!(buffer.back_n(4), None);
+        buffer.pop_back();
+        assert_eq!(buffer.back_n(0), Some(&4));
+        assert_eq!(buffer.back_n(3), None);
+        assert_eq!(buffer.front_n(3), None);
+    }
+
+    #[test]
+    fn as_mut_slices_run_front_to_back() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(5);
+        buffer.extend(0..8);
+        let (first, second) = buffer.as_mut_slices();
+        assert_eq!([&first[..], &second[..]].concat(), vec![3, 4, 5, 6, 7]);
+        for (i, item) in first.iter_mut().chain(second.iter_mut()).enumerate() {
+            *item += 10 * i as i32;
+        }
+        assert_eq!(buffer.to_vec(), vec![3, 14, 25, 36, 47]);
+        buffer.swap(0, 4);
+        buffer.swap(1, 1);
+        assert_eq!(buffer.to_vec(), vec![47, 14, 25, 36, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "index out of bounds")]
+    fn swap_panics_past_the_length() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(5);
+        buffer.extend(0..3);
+        buffer.swap(0, 3);
+    }
+
+    #[test]
+    fn keep_last_and_keep_first_trim_to_n() {
+        for (n, last, first) in [(9, vec![0, 1, 2, 3, 4], vec![0, 1, 2, 3, 4]), (5, vec![0, 1, 2, 3, 4], vec![0, 1, 2, 3, 4]), (2, vec![3, 4], vec![0, 1]), (0, vec![], vec![])] {
+            let mut newest: RingBuffer<i32> = RingBuffer::new(6);
+            newest.extend(0..5);
+            newest.keep_last(n);
+            assert_eq!((newest.to_vec(), newest.len(), newest.capacity()), (last, n.min(5), 6));
+            let mut oldest: RingBuffer<i32> = RingBuffer::new(6);
+            oldest.extend(0..5);
+            oldest.keep_first(n);
+            assert_eq!((oldest.to_vec(), oldest.len(), oldest.capacity()), (first, n.min(5), 6));
+        }
+    }
+
+    #[test]
+    fn fill_overwrites_only_existing_elements() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(5);
+        buffer.extend(0..3);
+        buffer.fill(7);
+        assert_eq!(buffer.to_vec(), vec![7, 7, 7]);
+        assert_eq!((buffer.len(), buffer.capacity()), (3, 5));
+        let mut empty: RingBuffer<i32> = RingBuffer::new(5);
+        empty.fill(7);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    #[cfg(all(debug_assertions, not(feature="slice-ring-buffer")))]
+    #[should_panic(expected = "out of sync")]
+    fn push_back_catches_a_desynced_length() {
+        let mut buffer: RingBuffer<i32> = RingBuffer::new(4);
+        buffer.push_back(0);
+        buffer.inner_mut().push_back(1);
+        buffer.push_back(2);
+    }
+
+    #[test]
+    fn vec_round_trips_front_to_back() {
+        let buffer = RingBuffer::from(vec![1, 2, 3]);
+        assert_eq!((buffer.len(), buffer.capacity()), (3, 3));
+        assert_eq!(Vec::from(buffer), vec![1, 2, 3]);
+        // Wrapped around, the order is still front to back
+        let mut wrapped: RingBuffer<i32> = RingBuffer::new(4);
+        wrapped.extend(0..7);
+        assert_eq!(wrapped.to_vec(), vec![3, 4, 5, 6]);
+        let round_trip: Vec<i32> = RingBuffer::from(Vec::from(wrapped)).into();
+        assert_eq!(round_trip, vec![3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn map_converts_real_to_complex_and_back() {
+        use num_complex::Complex;
+        let mut real: RingBuffer<f64> = RingBuffer::new(4);
+        real.extend([1.0, 2.0, 3.0, 4.0, 5.0]);
+        real.pop_front();
+        let complex: RingBuffer<Complex<f64>> = real.clone().map(|sample| Complex::new(sample, -sample));
+        assert_eq!((complex.len(), complex.capacity()), (3, 4));
+        assert_eq!(complex.to_vec(), vec![Complex::new(3.0, -3.0), Complex::new(4.0, -4.0), Complex::new(5.0, -5.0)]);
+        let back: RingBuffer<f64> = complex.map(|sample| sample.re);
+        assert_eq!((back.len(), back.capacity()), (3, 4));
+        assert_eq!(back.to_vec(), real.to_vec());
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::{Arc, Mutex};
+use num_complex::Complex;
+use rustfft::{FftNum, num_traits::Zero};
+
+#[cfg(not(feature="realfft"))]
+use rustfft::Fft;
+/// The FFT planner shared by the convolution types
+#[cfg(not(feature="realfft"))]
+pub type Planner<T> = rustfft::FftPlanner<T>;
+
+#[cfg(feature="realfft")]
+use realfft::{ComplexToReal, RealToComplex};
+/// The FFT planner shared by the convolution types
+#[cfg(feature="realfft")]
+pub type Planner<T> = realfft::RealFftPlanner<T>;
+
+/// A planned forward/inverse transform pair of a fixed size, along with the
+///  scratch space needed to run it without allocating
+///
+/// With the default engine the spectrum holds all `len()` bins of a complex FFT.
+///  With the `realfft` feature only the `len() / 2 + 1` unique bins of the
+///  real-input FFT are kept.
+#[derive(Clone)]
+pub(crate) struct Transform<T> {
+    #[cfg(not(feature="realfft"))]
+    forward: Arc<dyn Fft<T>>,
+    #[cfg(not(feature="realfft"))]
+    inverse: Arc<dyn Fft<T>>,
+    #[cfg(feature="realfft")]
+    forward: Arc<dyn RealToComplex<T>>,
+    #[cfg(feature="realfft")]
+    inverse: Arc<dyn ComplexToReal<T>>,
+    len: usize,
+    scratch: Vec<Complex<T>>,
+}
+
+impl<T> Transform<T> where T: FftNum {
+    pub fn new(planner: &Arc<Mutex<Planner<T>>>, len: usize) -> Self {
+        let (forward, inverse) = {
+            let mut planner = planner.lock().unwrap();
+            (planner.plan_fft_forward(len), planner.plan_fft_inverse(len))
+        };
+        #[cfg(not(feature="realfft"))]
+        let scratch_len = forward.get_inplace_scratch_len().max(inverse.get_inplace_scratch_len());
+        #[cfg(feature="realfft")]
+        let scratch_len = forward.get_scratch_len().max(inverse.get_scratch_len());
+        Self {
+            forward,
+            inverse,
+            len,
+            scratch: vec![Complex::zero(); scratch_len],
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn spectrum_len(&self) -> usize {
+        spectrum_len(self.len)
+    }
+    /// Transforms `time` (of length `len()`) into `spectrum` (of length
+    ///  `spectrum_len()`)
+    ///
+    /// `time` is used as scratch space and its contents are unspecified afterwards.
+    #[cfg(not(feature="realfft"))]
+    pub fn forward(&mut self, time: &mut [T], spectrum: &mut [Complex<T>]) {
+        for (bin, sample) in spectrum.iter_mut().zip(time.iter()) {
+            *bin = Complex::new(*sample, T::zero());
+        }
+        self.forward.process_with_scratch(spectrum, &mut self.scratch);
+    }
+    #[cfg(feature="realfft")]
+    pub fn forward(&mut self, time: &mut [T], spectrum: &mut [Complex<T>]) {
+        self.forward.process_with_scratch(time, spectrum, &mut self.scratch).unwrap();
+    }
+    /// Transforms `spectrum` back into `time` without normalizing, so every
+    ///  output sample is scaled by `len()`
+    ///
+    /// `spectrum` is used as scratch space and its contents are unspecified afterwards.
+    ///
+    /// Note
+    /// ====
+    /// Every spectrum passed in here is a product of spectra of real signals, so it
+    ///  is conjugate-symmetric and its inverse is real: the imaginary parts are only
+    ///  rounding noise. Taking the real part is therefore exact, whereas taking the
+    ///  magnitude would rectify every negative sample of the convolution.
+    #[cfg(not(feature="realfft"))]
+    pub fn inverse(&mut self, spectrum: &mut [Complex<T>], time: &mut [T]) {
+        self.inverse.process_with_scratch(spectrum, &mut self.scratch);
+        for (sample, bin) in time.iter_mut().zip(spectrum.iter()) {
+            *sample = bin.re;
+        }
+    }
+    #[cfg(feature="realfft")]
+    pub fn inverse(&mut self, spectrum: &mut [Complex<T>], time: &mut [T]) {
+        // The DC and Nyquist bins of a real signal are purely real; discard any
+        //  rounding noise in their imaginary parts so the planner accepts them
+        spectrum[0].im = T::zero();
+        if self.len.is_multiple_of(2) {
+            spectrum[self.len / 2].im = T::zero();
+        }
+        self.inverse.process_with_scratch(spectrum, time, &mut self.scratch).unwrap();
+    }
+}
+
+/// Plans the forward and inverse FFTs of every size in `sizes` up front, so that
+///  convolvers later created with `planner` find them already planned
+///
+/// The sizes are FFT sizes, as reported by `FFTConvolution::fft_size`, not window
+///  sizes. Planning allocates and can take a while for large sizes, so this must
+///  be called off the real-time path, before playback starts.
+pub fn prewarm_planner<T>(planner: &Arc<Mutex<Planner<T>>>, sizes: &[usize]) where T: FftNum {
+    let mut planner = planner.lock().unwrap();
+    for size in sizes.iter() {
+        planner.plan_fft_forward(*size);
+        planner.plan_fft_inverse(*size);
+    }
+}
+
+/// The number of spectrum bins kept for a transform of size `len`
+pub(crate) fn spectrum_len(len: usize) -> usize {
+    if cfg!(feature="realfft") {
+        len / 2 + 1
+    } else {
+        len
+    }
+}
+
+#[cfg(all(test, not(feature="realfft")))]
+mod tests {
+    use super::*;
+    use crate::diagnostics::{naive_convolve, white_noise};
+
+    /// Mirrors `FFTConvolution::convolve_chunk`, checking the imaginary parts that
+    ///  `Transform::inverse` discards
+    #[test]
+    fn inverse_of_a_product_of_real_spectra_is_real() {
+        let signal = white_noise(256, 1);
+        let ir = white_noise(300, 2);
+        let len = (signal.len() + ir.len() - 1).next_power_of_two();
+        let mut transform = Transform::new(&Arc::new(Mutex::new(Planner::new())), len);
+        let mut spectra = [&signal, &ir].map(|samples| {
+            let mut time = samples.clone();
+            time.resize(len, 0.0);
+            let mut spectrum = vec![Complex::zero(); len];
+            transform.forward(&mut time, &mut spectrum);
+            spectrum
+        });
+        let [product, ir_spectrum] = &mut spectra;
+        for (val, ir_val) in product.iter_mut().zip(ir_spectrum.iter()) {
+            *val *= *ir_val;
+        }
+        transform.inverse.process_with_scratch(product, &mut transform.scratch);
+        let scale = 1.0 / len as f64;
+        for bin in product.iter() {
+            assert!((bin.im * scale).abs() < 1e-9);
+        }
+        for (bin, expected) in product.iter().zip(naive_convolve(&signal, &ir)) {
+            assert!((bin.re * scale - expected).abs() < 1e-9);
+        }
+    }
+}
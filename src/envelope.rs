@@ -0,0 +1,91 @@
+use std::f64::consts::PI;
+use rustfft::num_traits::Float;
+
+use crate::{DirectConvolution, Filter};
+use crate::dtype::RingBuffer;
+use crate::window::blackman;
+
+/// Half the length of the Hilbert FIR, which is also the latency of the envelope
+const HILBERT_HALF_LEN: usize = 63;
+
+/// Follows the amplitude envelope of a signal, for instance the wet output of an
+///  `FFTConvolution` to drive ducking or auto-gain
+///
+/// The analytic signal is built from the input delayed by `latency` samples and
+///  its Hilbert transform, taken with a Blackman-windowed FIR of
+///  `2 * latency + 1` taps run through a `DirectConvolution`. Its magnitude is
+///  then smoothed with one-pole attack and release filters.
+///
+/// Note
+/// ====
+/// The FIR is short, so the envelope of anything below a few hundred hertz at
+///  common sample rates ripples at twice the signal frequency.
+pub struct EnvelopeFollower<T = f64> where T: Float {
+    hilbert: DirectConvolution<T>,
+    delay: RingBuffer<T>,
+    attack: T,
+    release: T,
+    envelope: T,
+}
+impl<T> EnvelopeFollower<T> where T: Float {
+    /// Creates the follower, `attack` and `release` being the time constants of
+    ///  the smoothing in samples
+    ///
+    /// A time constant of 0 follows the magnitude of the analytic signal directly.
+    pub fn new(attack: usize, release: usize) -> EnvelopeFollower<T> {
+        let window = blackman(2 * HILBERT_HALF_LEN + 1);
+        let ir = window.iter().enumerate().map(|(i, w)| {
+            let k = i as isize - HILBERT_HALF_LEN as isize;
+            if k % 2 == 0 {
+                T::zero()
+            } else {
+                T::from(2.0 / (PI * k as f64) * w).unwrap()
+            }
+        }).collect();
+        EnvelopeFollower {
+            hilbert: DirectConvolution::new(ir),
+            delay: RingBuffer::new(HILBERT_HALF_LEN + 1).initialize(T::zero()),
+            attack: Self::coefficient(attack),
+            release: Self::coefficient(release),
+            envelope: T::zero(),
+        }
+    }
+    fn coefficient(time_constant: usize) -> T {
+        if time_constant == 0 {
+            T::zero()
+        } else {
+            T::from((-1.0 / time_constant as f64).exp()).unwrap()
+        }
+    }
+    /// The delay of the envelope relative to the input, in samples
+    pub fn latency(&self) -> usize {
+        HILBERT_HALF_LEN
+    }
+    /// The last envelope value returned by `compute`
+    pub fn envelope(&self) -> T {
+        self.envelope
+    }
+}
+impl<T> Filter<T> for EnvelopeFollower<T> where T: Float {
+    fn clear(&mut self) {
+        self.hilbert.clear();
+        self.delay.clear();
+        self.delay.initialize_again(T::zero());
+        self.envelope = T::zero();
+    }
+    fn compute(&mut self, signal: T) -> T {
+        let imaginary = self.hilbert.compute(signal);
+        self.delay.push_back(signal);
+        let real = *self.delay.front().unwrap();
+        let magnitude = real.hypot(imaginary);
+        let coefficient = if magnitude > self.envelope { self.attack } else { self.release };
+        self.envelope = magnitude + coefficient * (self.envelope - magnitude);
+        self.envelope
+    }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+    fn flush(&mut self) -> Vec<T> {
+        (0..self.latency()).map(|_| self.compute(T::zero())).collect()
+    }
+}
@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// Errors returned by the checked constructors of the convolution types
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConvolutionError {
+    /// The impulse response has no samples
+    EmptyIr,
+    /// The window size is 0, so the input could never be chunked
+    ZeroWindow,
+    /// A buffer does not have the length it must have
+    LengthMismatch { expected: usize, found: usize },
+    /// The FFT size needed for this IR and window size does not fit in a `usize`
+    Overflow { ir_len: usize, window_size: usize },
+    /// The samples of an impulse response are stored in a format that cannot be
+    ///  read, like a WAV file of an unusual bit depth
+    UnsupportedFormat { bits_per_sample: u16, float: bool },
+    /// A file could not be read or written, with a description of why
+    File(String),
+}
+impl fmt::Display for ConvolutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvolutionError::EmptyIr => write!(f, "the impulse response is empty"),
+            ConvolutionError::ZeroWindow => write!(f, "the window size must be greater than 0"),
+            ConvolutionError::LengthMismatch { expected, found } => write!(f, "expected a length of {}, found {}", expected, found),
+            ConvolutionError::Overflow { ir_len, window_size } => {
+                write!(f, "an IR of {} samples with a window size of {} needs an FFT larger than usize::MAX", ir_len, window_size)
+            },
+            ConvolutionError::UnsupportedFormat { bits_per_sample, float } => {
+                write!(f, "unsupported sample format: {}-bit {}", bits_per_sample, if *float { "float" } else { "integer" })
+            },
+            ConvolutionError::File(message) => write!(f, "{}", message),
+        }
+    }
+}
+impl std::error::Error for ConvolutionError {}
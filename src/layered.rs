@@ -0,0 +1,95 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{ConvolutionError, FFTConvolution, Filter};
+use crate::dtype::RingBuffer;
+use crate::engine::Planner;
+
+struct Layer {
+    convolution: FFTConvolution,
+    gain: f64,
+    /// Makes up the difference between the latency of this layer and the
+    ///  largest one
+    delay: RingBuffer<f64>,
+}
+
+/// Several IRs convolved in parallel with the same input, each at its own gain,
+///  and summed
+///
+/// Every layer can use its own window size, for instance a small one for the
+///  early reflections and a large one for a long tail. The output of each layer
+///  is delayed so that they all line up with the layer of the largest latency,
+///  which is the latency of the whole filter, and the sum is the same as
+///  convolving with the gain-weighted sum of the IRs. All layers share one FFT
+///  planner.
+pub struct LayeredConvolution {
+    layers: Vec<Layer>,
+    latency: usize,
+}
+impl LayeredConvolution {
+    /// Creates the filter from `(ir, window_size, gain)` triples, panicking where
+    ///  `try_new` would return an error
+    pub fn new(layers: Vec<(Vec<f64>, usize, f64)>) -> LayeredConvolution {
+        Self::try_new(layers).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter from `(ir, window_size, gain)` triples, checking each IR
+    ///  and window size as `FFTConvolution::try_new` does
+    pub fn try_new(layers: Vec<(Vec<f64>, usize, f64)>) -> Result<LayeredConvolution, ConvolutionError> {
+        for (ir, window_size, _) in layers.iter() {
+            FFTConvolution::<f64>::checked_padded_window_size(ir.len(), *window_size)?;
+        }
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        let convolutions: Vec<(FFTConvolution, f64)> = layers.into_iter()
+            .map(|(ir, window_size, gain)| (FFTConvolution::with_planner(ir, window_size, Arc::clone(&planner)), gain))
+            .collect();
+        let latency = convolutions.iter().map(|(convolution, _)| convolution.latency()).max().unwrap_or(0);
+        let layers = convolutions.into_iter().map(|(convolution, gain)| Layer {
+            delay: RingBuffer::new(latency - convolution.latency()).initialize(0.0),
+            convolution,
+            gain,
+        }).collect();
+        Ok(LayeredConvolution {
+            layers,
+            latency,
+        })
+    }
+    /// The number of layers
+    pub fn layers(&self) -> usize {
+        self.layers.len()
+    }
+    /// The largest latency of all layers, to which the others are aligned
+    pub fn latency(&self) -> usize {
+        self.latency
+    }
+    /// Changes the gain of the layer at `index`, taking effect from the next
+    ///  output sample
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_layer_gain(&mut self, index: usize, gain: f64) {
+        self.layers[index].gain = gain;
+    }
+    /// Panics if `index` is out of bounds.
+    pub fn layer_gain(&self, index: usize) -> f64 {
+        self.layers[index].gain
+    }
+}
+impl Filter for LayeredConvolution {
+    fn clear(&mut self) {
+        for layer in self.layers.iter_mut() {
+            layer.convolution.clear();
+            layer.delay.clear();
+            layer.delay.initialize_again(0.0);
+        }
+    }
+    fn compute(&mut self, signal: f64) -> f64 {
+        self.layers.iter_mut().fold(0.0, |acc, layer| {
+            acc + layer.delay.shift(layer.convolution.compute(signal)) * layer.gain
+        })
+    }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+    fn flush(&mut self) -> Vec<f64> {
+        let ir_len = self.layers.iter().map(|layer| layer.convolution.ir_len()).max().unwrap_or(0);
+        (0..(self.latency + ir_len).saturating_sub(1)).map(|_| self.compute(0.0)).collect()
+    }
+}
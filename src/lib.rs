@@ -1,13 +1,54 @@
-use std::{iter, sync::{Arc, Mutex}};
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
 use num_complex::{Complex};
-use rustfft::{FftPlanner, num_traits::{Zero}};
+use rustfft::{FftNum, num_traits::{Float, Zero}};
 
 pub mod dtype;
-use crate::dtype::{ChunkedBuffer, RingBuffer};
+pub mod window;
+#[cfg(feature="test-util")]
+pub mod test_util;
+mod diagnostics;
+pub use crate::diagnostics::verify_against_naive;
+mod error;
+pub use crate::error::ConvolutionError;
+#[cfg(feature="hound")]
+pub mod wav;
+#[cfg(feature="hound")]
+pub use crate::wav::{IrLoadError, convolve_wav_to_wav, load_ir_wav, load_ir_wav_resampled, load_stereo_ir_wav};
+use crate::dtype::RingBuffer;
+mod engine;
+use crate::engine::Transform;
+pub use crate::engine::{Planner, prewarm_planner};
+mod partitioned;
+pub use crate::partitioned::{NonUniformPartitionedConvolution, PartitionedFFTConvolution};
+mod multichannel;
+pub use crate::multichannel::{ConvolutionMatrix, MultiChannelFFTConvolution, MultiChannelFilter};
+mod complex;
+pub use crate::complex::ComplexFFTConvolution;
+mod envelope;
+pub use crate::envelope::EnvelopeFollower;
+mod layered;
+pub use crate::layered::LayeredConvolution;
+#[cfg(test)]
+mod tests;
 
 pub trait StereoFilter {
     fn clear(&mut self);
+    /// Alias of `clear`, for parity with other DSP crates
+    fn reset(&mut self) {
+        self.clear();
+    }
     fn compute(&mut self, signal: (f64, f64)) -> (f64, f64);
+    /// Processes a whole block of stereo samples
+    ///
+    /// The default implementation calls `compute` on each sample in sequence;
+    ///  implementors can override it to batch their internal processing.
+    fn process_block(&mut self, input: &[(f64, f64)], output: &mut [(f64, f64)]) {
+        assert_eq!(input.len(), output.len(), "input and output must be the same length");
+        for (signal, out_ref) in input.iter().zip(output.iter_mut()) {
+            *out_ref = self.compute(*signal);
+        }
+    }
 }
 
 pub struct TrueStereoFFTConvolution {
@@ -15,15 +56,31 @@ pub struct TrueStereoFFTConvolution {
     rr: FFTConvolution,
     lr: FFTConvolution,
     rl: FFTConvolution,
+    block_scratch: Vec<f64>,
+    cross_scratch: Vec<f64>,
+    crossfeed: f64,
 }
 impl TrueStereoFFTConvolution {
+    /// Creates the filter, panicking where `try_new` would return an error
     pub fn new(ir_ll: Vec<f64>, ir_rr: Vec<f64>, ir_lr: Vec<f64>, ir_rl: Vec<f64>, window_size: usize) -> TrueStereoFFTConvolution {
-        TrueStereoFFTConvolution {
-            ll: FFTConvolution::new(ir_ll, window_size),
-            rr: FFTConvolution::new(ir_rr, window_size),
-            lr: FFTConvolution::new(ir_lr, window_size),
-            rl: FFTConvolution::new(ir_rl, window_size)
+        Self::try_new(ir_ll, ir_rr, ir_lr, ir_rl, window_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking each of the four IRs as
+    ///  `FFTConvolution::try_new` does
+    pub fn try_new(ir_ll: Vec<f64>, ir_rr: Vec<f64>, ir_lr: Vec<f64>, ir_rl: Vec<f64>, window_size: usize) -> Result<TrueStereoFFTConvolution, ConvolutionError> {
+        for ir in [&ir_ll, &ir_rr, &ir_lr, &ir_rl] {
+            FFTConvolution::<f64>::checked_padded_window_size(ir.len(), window_size)?;
         }
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        Ok(TrueStereoFFTConvolution {
+            ll: FFTConvolution::with_planner(ir_ll, window_size, Arc::clone(&planner)),
+            rr: FFTConvolution::with_planner(ir_rr, window_size, Arc::clone(&planner)),
+            lr: FFTConvolution::with_planner(ir_lr, window_size, Arc::clone(&planner)),
+            rl: FFTConvolution::with_planner(ir_rl, window_size, planner),
+            block_scratch: Vec::new(),
+            cross_scratch: Vec::new(),
+            crossfeed: 1.0,
+        })
     }
     pub fn window_size(&self) -> usize {
         self.ll.window_size()
@@ -31,6 +88,68 @@ impl TrueStereoFFTConvolution {
     pub fn internal_buffer_size(&self) -> usize {
         self.ll.internal_buffer_size()
     }
+    pub fn latency(&self) -> usize {
+        self.ll.latency()
+    }
+    /// Scales the cross terms `lr` and `rl`, from 0.0 (the channels are convolved
+    ///  independently, like `StereoFFTConvolution`) to 1.0 (full true stereo, the
+    ///  default)
+    pub fn set_crossfeed(&mut self, amount: f64) {
+        self.crossfeed = amount;
+    }
+    pub fn crossfeed(&self) -> f64 {
+        self.crossfeed
+    }
+    /// Clears only the response of the left output to the left input
+    pub fn clear_ll(&mut self) {
+        self.ll.clear();
+    }
+    /// Clears only the response of the right output to the right input
+    pub fn clear_rr(&mut self) {
+        self.rr.clear();
+    }
+    /// Clears only the response of the right output to the left input
+    pub fn clear_lr(&mut self) {
+        self.lr.clear();
+    }
+    /// Clears only the response of the left output to the right input
+    pub fn clear_rl(&mut self) {
+        self.rl.clear();
+    }
+    /// Processes de-interleaved channel buffers, all four of which must be the
+    ///  same length
+    ///
+    /// The four convolutions run on whole channel buffers through
+    ///  `FFTConvolution::process_block`, and with the `rayon` feature they are run
+    ///  in parallel. Either way the output is identical to calling `compute` on each
+    ///  sample in sequence.
+    pub fn process_planar(&mut self, left_in: &[f64], right_in: &[f64], left_out: &mut [f64], right_out: &mut [f64]) {
+        let n = left_in.len();
+        assert!(right_in.len() == n && left_out.len() == n && right_out.len() == n, "all channel buffers must be the same length");
+        self.cross_scratch.resize(2 * n, 0.0);
+        let (lr_out, rl_out) = self.cross_scratch.split_at_mut(n);
+
+        let (ll, rr, lr, rl) = (&mut self.ll, &mut self.rr, &mut self.lr, &mut self.rl);
+        #[cfg(feature="rayon")]
+        rayon::join(
+            || rayon::join(|| ll.process_block(left_in, left_out), || rl.process_block(right_in, rl_out)),
+            || rayon::join(|| rr.process_block(right_in, right_out), || lr.process_block(left_in, lr_out)),
+        );
+        #[cfg(not(feature="rayon"))]
+        {
+            ll.process_block(left_in, left_out);
+            rl.process_block(right_in, rl_out);
+            rr.process_block(right_in, right_out);
+            lr.process_block(left_in, lr_out);
+        }
+
+        for (out_ref, rl_val) in left_out.iter_mut().zip(rl_out.iter()) {
+            *out_ref += *rl_val * self.crossfeed;
+        }
+        for (out_ref, lr_val) in right_out.iter_mut().zip(lr_out.iter()) {
+            *out_ref += *lr_val * self.crossfeed;
+        }
+    }
 }
 impl StereoFilter for TrueStereoFFTConvolution {
     fn clear(&mut self) {
@@ -40,20 +159,67 @@ impl StereoFilter for TrueStereoFFTConvolution {
         self.rl.clear();
     }
     fn compute(&mut self, signal: (f64, f64)) -> (f64, f64) {
-        (self.ll.compute(signal.0) + self.rl.compute(signal.1),
-        self.rr.compute(signal.1) + self.lr.compute(signal.0))
+        (self.ll.compute(signal.0) + self.rl.compute(signal.1) * self.crossfeed,
+        self.rr.compute(signal.1) + self.lr.compute(signal.0) * self.crossfeed)
+    }
+    fn process_block(&mut self, input: &[(f64, f64)], output: &mut [(f64, f64)]) {
+        assert_eq!(input.len(), output.len(), "input and output must be the same length");
+        let n = input.len();
+        // Scratch layout: left in, right in, left out, right out
+        let mut block_scratch = std::mem::take(&mut self.block_scratch);
+        block_scratch.resize(4 * n, 0.0);
+        let (inputs, outputs) = block_scratch.split_at_mut(2 * n);
+        let (left_in, right_in) = inputs.split_at_mut(n);
+        let (left_out, right_out) = outputs.split_at_mut(n);
+        for ((left, right), signal) in left_in.iter_mut().zip(right_in.iter_mut()).zip(input.iter()) {
+            *left = signal.0;
+            *right = signal.1;
+        }
+        self.process_planar(left_in, right_in, left_out, right_out);
+        for ((out_ref, left), right) in output.iter_mut().zip(left_out.iter()).zip(right_out.iter()) {
+            *out_ref = (*left, *right);
+        }
+        self.block_scratch = block_scratch;
     }
 }
 
 pub struct StereoFFTConvolution {
     ll: FFTConvolution,
     rr: FFTConvolution,
+    block_scratch: Vec<f64>,
+    width: f64,
 }
 impl StereoFFTConvolution {
+    /// Creates the filter, panicking where `try_new` would return an error
     pub fn new(ir_left: Vec<f64>, ir_right: Vec<f64>, window_size: usize) -> StereoFFTConvolution {
+        Self::try_new(ir_left, ir_right, window_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking both IRs as `FFTConvolution::try_new` does
+    pub fn try_new(ir_left: Vec<f64>, ir_right: Vec<f64>, window_size: usize) -> Result<StereoFFTConvolution, ConvolutionError> {
+        for ir in [&ir_left, &ir_right] {
+            FFTConvolution::<f64>::checked_padded_window_size(ir.len(), window_size)?;
+        }
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        Ok(StereoFFTConvolution {
+            ll: FFTConvolution::with_planner(ir_left, window_size, Arc::clone(&planner)),
+            rr: FFTConvolution::with_planner(ir_right, window_size, planner),
+            block_scratch: Vec::new(),
+            width: 1.0,
+        })
+    }
+    /// Convolves both channels with the same mono IR
+    ///
+    /// The IR's FFT is planned and computed once and its spectrum is shared by
+    ///  both channels, which otherwise behave exactly as if each had been given
+    ///  its own copy of `ir`.
+    pub fn mono_ir(ir: Vec<f64>, window_size: usize) -> StereoFFTConvolution {
+        let ll = FFTConvolution::new(ir, window_size);
+        let rr = ll.sharing_ir();
         StereoFFTConvolution {
-            ll: FFTConvolution::new(ir_left, window_size),
-            rr: FFTConvolution::new(ir_right, window_size)
+            ll,
+            rr,
+            block_scratch: Vec::new(),
+            width: 1.0,
         }
     }
     pub fn window_size(&self) -> usize {
@@ -62,6 +228,84 @@ impl StereoFFTConvolution {
     pub fn internal_buffer_size(&self) -> usize {
         self.ll.internal_buffer_size()
     }
+    pub fn latency(&self) -> usize {
+        self.ll.latency()
+    }
+    /// Sets the stereo width of the convolved signal, 1.0 by default
+    ///
+    /// The output is converted to mid/side, the side is scaled by `width` and
+    ///  the result converted back, so 1.0 leaves it untouched, 0.0 collapses it to
+    ///  mono and anything above 1.0 widens it.
+    pub fn set_width(&mut self, width: f64) {
+        self.width = width;
+    }
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+    /// Clears only the left channel, leaving the right one untouched
+    pub fn clear_left(&mut self) {
+        self.ll.clear();
+    }
+    /// Clears only the right channel, leaving the left one untouched
+    pub fn clear_right(&mut self) {
+        self.rr.clear();
+    }
+    /// Retrieves the rest of the convolution of both channels after the end of a
+    ///  finite signal, as `FFTConvolution::flush` does
+    ///
+    /// When the two IRs differ in length, the channel with the shorter one is
+    ///  padded with zeros to the length of the longer tail.
+    pub fn flush(&mut self) -> Vec<(f64, f64)> {
+        let mut left = self.ll.flush();
+        let mut right = self.rr.flush();
+        let len = left.len().max(right.len());
+        left.resize(len, 0.0);
+        right.resize(len, 0.0);
+        left.into_iter().zip(right).map(|output| {
+            if self.width != 1.0 {
+                apply_width(output, self.width)
+            } else {
+                output
+            }
+        }).collect()
+    }
+    /// Processes de-interleaved channel buffers, all four of which must be the
+    ///  same length
+    pub fn process_planar(&mut self, left_in: &[f64], right_in: &[f64], left_out: &mut [f64], right_out: &mut [f64]) {
+        let n = left_in.len();
+        assert!(right_in.len() == n && left_out.len() == n && right_out.len() == n, "all channel buffers must be the same length");
+        self.ll.process_block(left_in, left_out);
+        self.rr.process_block(right_in, right_out);
+        if self.width != 1.0 {
+            for (left, right) in left_out.iter_mut().zip(right_out.iter_mut()) {
+                (*left, *right) = apply_width((*left, *right), self.width);
+            }
+        }
+    }
+    /// Processes an interleaved buffer `[left, right, left, right, ...]` in place
+    ///
+    /// The result is the same as running each `(left, right)` frame through
+    ///  `compute`. Panics if `buffer` has an odd length.
+    pub fn process_interleaved(&mut self, buffer: &mut [f64]) {
+        assert!(buffer.len().is_multiple_of(2), "an interleaved stereo buffer must have an even length");
+        let n = buffer.len() / 2;
+        // Scratch layout: left, right
+        self.block_scratch.resize(2 * n, 0.0);
+        let (left, right) = self.block_scratch.split_at_mut(n);
+        for ((left, right), frame) in left.iter_mut().zip(right.iter_mut()).zip(buffer.chunks_exact(2)) {
+            *left = frame[0];
+            *right = frame[1];
+        }
+        self.ll.process_inplace(left);
+        self.rr.process_inplace(right);
+        for ((left, right), frame) in left.iter().zip(right.iter()).zip(buffer.chunks_exact_mut(2)) {
+            (frame[0], frame[1]) = if self.width != 1.0 {
+                apply_width((*left, *right), self.width)
+            } else {
+                (*left, *right)
+            };
+        }
+    }
 }
 impl StereoFilter for StereoFFTConvolution {
     fn clear(&mut self) {
@@ -69,85 +313,1718 @@ impl StereoFilter for StereoFFTConvolution {
         self.rr.clear();
     }
     fn compute(&mut self, signal: (f64, f64)) -> (f64, f64) {
-        (self.ll.compute(signal.0), self.rr.compute(signal.1))
+        let output = (self.ll.compute(signal.0), self.rr.compute(signal.1));
+        if self.width != 1.0 {
+            apply_width(output, self.width)
+        } else {
+            output
+        }
+    }
+    fn process_block(&mut self, input: &[(f64, f64)], output: &mut [(f64, f64)]) {
+        assert_eq!(input.len(), output.len(), "input and output must be the same length");
+        let n = input.len();
+        // Scratch layout: left in, right in, left out, right out
+        self.block_scratch.resize(4 * n, 0.0);
+        let (inputs, outputs) = self.block_scratch.split_at_mut(2 * n);
+        let (left_in, right_in) = inputs.split_at_mut(n);
+        let (left_out, right_out) = outputs.split_at_mut(n);
+        for ((left, right), signal) in left_in.iter_mut().zip(right_in.iter_mut()).zip(input.iter()) {
+            *left = signal.0;
+            *right = signal.1;
+        }
+        self.ll.process_block(left_in, left_out);
+        self.rr.process_block(right_in, right_out);
+        for ((out_ref, left), right) in output.iter_mut().zip(left_out.iter()).zip(right_out.iter()) {
+            *out_ref = (*left, *right);
+        }
+        if self.width != 1.0 {
+            for out_ref in output.iter_mut() {
+                *out_ref = apply_width(*out_ref, self.width);
+            }
+        }
+    }
+}
+
+/// Scales the side signal of `signal` by `width`
+fn apply_width(signal: (f64, f64), width: f64) -> (f64, f64) {
+    let mid = (signal.0 + signal.1) * 0.5;
+    let side = (signal.0 - signal.1) * 0.5 * width;
+    (mid + side, mid - side)
+}
+
+/// Convolves a mono input with a stereo IR, producing a stereo output
+pub struct MonoToStereoConvolution {
+    left: FFTConvolution,
+    right: FFTConvolution,
+}
+impl MonoToStereoConvolution {
+    pub fn new(ir_left: Vec<f64>, ir_right: Vec<f64>, window_size: usize) -> MonoToStereoConvolution {
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        MonoToStereoConvolution {
+            left: FFTConvolution::with_planner(ir_left, window_size, Arc::clone(&planner)),
+            right: FFTConvolution::with_planner(ir_right, window_size, planner),
+        }
+    }
+    pub fn window_size(&self) -> usize {
+        self.left.window_size()
+    }
+    pub fn latency(&self) -> usize {
+        self.left.latency()
+    }
+    pub fn clear(&mut self) {
+        self.left.clear();
+        self.right.clear();
+    }
+    pub fn compute(&mut self, signal: f64) -> (f64, f64) {
+        (self.left.compute(signal), self.right.compute(signal))
+    }
+}
+
+/// Convolves the sum of both input channels with a mono IR and returns the
+///  result on both output channels
+///
+/// This is half the cost of a `StereoFFTConvolution`, for sends where a mono
+///  tail is enough.
+pub struct MonoSumConvolution {
+    convolution: FFTConvolution,
+    average: bool,
+}
+impl MonoSumConvolution {
+    pub fn new(ir: Vec<f64>, window_size: usize) -> MonoSumConvolution {
+        MonoSumConvolution {
+            convolution: FFTConvolution::new(ir, window_size),
+            average: false,
+        }
+    }
+    pub fn window_size(&self) -> usize {
+        self.convolution.window_size()
+    }
+    pub fn latency(&self) -> usize {
+        self.convolution.latency()
+    }
+    /// Scales the sum by 0.5 before convolving it, so that a centered signal
+    ///  comes out at the same level as the mono convolution of either channel,
+    ///  off by default
+    pub fn set_average(&mut self, average: bool) {
+        self.average = average;
+    }
+    pub fn averages(&self) -> bool {
+        self.average
+    }
+}
+impl StereoFilter for MonoSumConvolution {
+    fn clear(&mut self) {
+        self.convolution.clear();
+    }
+    fn compute(&mut self, signal: (f64, f64)) -> (f64, f64) {
+        let sum = signal.0 + signal.1;
+        let output = self.convolution.compute(if self.average { sum * 0.5 } else { sum });
+        (output, output)
+    }
+}
+
+/// Convolves the mid and side components of a stereo signal with separate IRs
+///
+/// The input is encoded as `mid = (left + right) / 2` and
+///  `side = (left - right) / 2`, each is convolved with its own IR, and the
+///  results are decoded back as `(mid + side, mid - side)`. With the same IR for
+///  both, this is the same as convolving each channel with it.
+pub struct MidSideFFTConvolution {
+    mid: FFTConvolution,
+    side: FFTConvolution,
+}
+impl MidSideFFTConvolution {
+    /// Creates the filter, panicking where `try_new` would return an error
+    pub fn new(ir_mid: Vec<f64>, ir_side: Vec<f64>, window_size: usize) -> MidSideFFTConvolution {
+        Self::try_new(ir_mid, ir_side, window_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking both IRs as `FFTConvolution::try_new` does
+    pub fn try_new(ir_mid: Vec<f64>, ir_side: Vec<f64>, window_size: usize) -> Result<MidSideFFTConvolution, ConvolutionError> {
+        for ir in [&ir_mid, &ir_side] {
+            FFTConvolution::<f64>::checked_padded_window_size(ir.len(), window_size)?;
+        }
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        Ok(MidSideFFTConvolution {
+            mid: FFTConvolution::with_planner(ir_mid, window_size, Arc::clone(&planner)),
+            side: FFTConvolution::with_planner(ir_side, window_size, planner),
+        })
+    }
+    pub fn window_size(&self) -> usize {
+        self.mid.window_size()
+    }
+    pub fn latency(&self) -> usize {
+        self.mid.latency()
+    }
+}
+impl StereoFilter for MidSideFFTConvolution {
+    fn clear(&mut self) {
+        self.mid.clear();
+        self.side.clear();
+    }
+    fn compute(&mut self, signal: (f64, f64)) -> (f64, f64) {
+        let mid = self.mid.compute((signal.0 + signal.1) * 0.5);
+        let side = self.side.compute((signal.0 - signal.1) * 0.5);
+        (mid + side, mid - side)
     }
 }
 
-pub trait Filter {
+/// Treats two independent mono filters as one stereo filter, routing the left
+///  channel through `left` and the right channel through `right`
+pub struct SplitStereo<L, R> where L: Filter, R: Filter {
+    left: L,
+    right: R,
+}
+impl<L, R> SplitStereo<L, R> where L: Filter, R: Filter {
+    pub fn new(left: L, right: R) -> SplitStereo<L, R> {
+        SplitStereo { left, right }
+    }
+    pub fn left(&mut self) -> &mut L {
+        &mut self.left
+    }
+    pub fn right(&mut self) -> &mut R {
+        &mut self.right
+    }
+    pub fn into_inner(self) -> (L, R) {
+        (self.left, self.right)
+    }
+}
+impl<L, R> StereoFilter for SplitStereo<L, R> where L: Filter, R: Filter {
+    fn clear(&mut self) {
+        self.left.clear();
+        self.right.clear();
+    }
+    fn compute(&mut self, signal: (f64, f64)) -> (f64, f64) {
+        (self.left.compute(signal.0), self.right.compute(signal.1))
+    }
+}
+
+/// A single-channel filter processing one sample at a time
+///
+/// The trait is object-safe, and every filter in this crate is `Send`, so they
+///  can be stored as `Box<dyn Filter + Send>` and moved to an audio thread. The
+///  FFT planner they hold is shared through an `Arc<Mutex<_>>`, and the planned
+///  FFTs themselves are `Send + Sync`.
+pub trait Filter<T = f64> {
     fn clear(&mut self);
-    fn compute(&mut self, signal: f64) -> f64;
+    fn compute(&mut self, signal: T) -> T;
+    /// Alias of `clear`, for parity with other DSP crates
+    fn reset(&mut self) {
+        self.clear();
+    }
+    /// The delay in samples between a sample entering `compute` and its
+    ///  contribution first appearing in the output, 0 by default
+    ///
+    /// The filters in this crate that have an inherent `latency` forward to it.
+    fn latency(&self) -> usize {
+        0
+    }
+    /// Drains whatever output is still pending once the input has stopped, as if
+    ///  zeros had been fed in, empty by default
+    fn flush(&mut self) -> Vec<T> {
+        Vec::new()
+    }
+}
+
+impl<T, F> Filter<T> for Box<F> where F: Filter<T> + ?Sized {
+    fn clear(&mut self) {
+        (**self).clear();
+    }
+    fn compute(&mut self, signal: T) -> T {
+        (**self).compute(signal)
+    }
+    fn latency(&self) -> usize {
+        (**self).latency()
+    }
+    fn flush(&mut self) -> Vec<T> {
+        (**self).flush()
+    }
+}
+
+/// Iterator adapters available on every `Filter`
+pub trait FilterExt<T = f64>: Filter<T> + Sized {
+    /// Lazily filters `input`, yielding the output of `compute` for each of its
+    ///  samples and then the `flush` tail once it runs out
+    ///
+    /// The first `latency()` samples of priming are skipped, so the convolutions
+    ///  in this crate yield exactly the `input.len() + ir_len() - 1` samples of the
+    ///  convolution. A filter with a latency but an empty `flush` is instead fed
+    ///  `latency()` default values at the end, so that it still yields
+    ///  `input.len()` samples.
+    fn filter_iter<I>(self, input: I) -> FilteredSignal<Self, I, T> where I: Iterator<Item = T> {
+        FilteredSignal {
+            skip: self.latency(),
+            filter: self,
+            input,
+            tail: None,
+        }
+    }
+}
+impl<T, F> FilterExt<T> for F where F: Filter<T> {}
+
+// Checked at compile time so that a non-`Send` field can't slip in unnoticed
+const fn assert_send<T: Send>() {}
+const _: () = {
+    assert_send::<FFTConvolution<f64>>();
+    assert_send::<FFTConvolution<f32>>();
+    assert_send::<DirectConvolution<f64>>();
+    assert_send::<OverlapSaveConvolution<f64>>();
+    assert_send::<PartitionedFFTConvolution<f64>>();
+    assert_send::<NonUniformPartitionedConvolution<f64>>();
+    assert_send::<StereoFFTConvolution>();
+    assert_send::<TrueStereoFFTConvolution>();
+    assert_send::<MonoToStereoConvolution>();
+    assert_send::<MonoSumConvolution>();
+    assert_send::<MidSideFFTConvolution>();
+    assert_send::<MultiChannelFFTConvolution>();
+    assert_send::<ConvolutionMatrix>();
+    assert_send::<ComplexFFTConvolution<f64>>();
+    assert_send::<EnvelopeFollower<f64>>();
+    assert_send::<LayeredConvolution>();
+};
+
+/// Computes the full linear convolution of `signal` and `ir` in one go
+///
+/// The result has `signal.len() + ir.len() - 1` samples, or none if either input
+///  is empty, and is computed with an `FFTConvolution` of the given `window_size`
+///  whose latency is trimmed off.
+pub fn convolve(signal: &[f64], ir: &[f64], window_size: usize) -> Vec<f64> {
+    if signal.is_empty() || ir.is_empty() {
+        return Vec::new();
+    }
+    let mut convolution = FFTConvolution::new(ir.to_vec(), window_size);
+    let mut output = vec![0.0; signal.len()];
+    convolution.process_block(signal, &mut output);
+    output.extend(convolution.flush());
+    output.drain(..convolution.latency());
+    output
+}
+
+/// Recovers the signal that `ir` was convolved with to give `output`
+///
+/// This is the inverse of `convolve`: the result has
+///  `output.len() - ir.len() + 1` samples, or none if `output` is shorter than
+///  `ir`. The whole signal is divided in the frequency domain at once as
+///  `Y / (H + regularization)`, with an FFT of `padded_window_size` for the IR and
+///  the longer of the signal and `window_size`. The `regularization` keeps the
+///  division bounded where `H` is close to zero, so use a small positive value
+///  unless `ir` is known to have no spectral nulls.
+pub fn deconvolve(output: &[f64], ir: &[f64], window_size: usize, regularization: f64) -> Vec<f64> {
+    if ir.is_empty() || output.len() < ir.len() {
+        return Vec::new();
+    }
+    let signal_len = output.len() - ir.len() + 1;
+    // Large enough that the linear convolution didn't wrap around
+    let padded_window_size = FFTConvolution::<f64>::padded_window_size(ir.len(), signal_len.max(window_size));
+    let mut transform = Transform::new(&Arc::new(Mutex::new(Planner::new())), padded_window_size);
+    let mut time = vec![0.0; padded_window_size];
+    let mut ir_spectrum = vec![Complex::zero(); transform.spectrum_len()];
+    time[..ir.len()].copy_from_slice(ir);
+    transform.forward(&mut time, &mut ir_spectrum);
+    let mut spectrum = vec![Complex::zero(); transform.spectrum_len()];
+    time.fill(0.0);
+    time[..output.len()].copy_from_slice(output);
+    transform.forward(&mut time, &mut spectrum);
+    for (val, ir_val) in spectrum.iter_mut().zip(ir_spectrum.iter()) {
+        *val /= *ir_val + regularization;
+    }
+    transform.inverse(&mut spectrum, &mut time);
+    time.truncate(signal_len);
+    for sample in time.iter_mut() {
+        *sample /= padded_window_size as f64;
+    }
+    time
+}
+
+/// Cascades two impulse responses into one
+///
+/// The result is the full linear convolution of `a` and `b`, with
+///  `a.len() + b.len() - 1` samples or none if either is empty, so that a single
+///  `FFTConvolution` with it sounds like the two IRs in series, with only one
+///  latency and one FFT per chunk. It is computed with a single FFT of both IRs.
+pub fn combine_irs(a: &[f64], b: &[f64]) -> Vec<f64> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let combined_len = a.len() + b.len() - 1;
+    let padded_window_size = FFTConvolution::<f64>::padded_window_size(a.len(), b.len());
+    let mut transform = Transform::new(&Arc::new(Mutex::new(Planner::new())), padded_window_size);
+    let mut time = vec![0.0; padded_window_size];
+    let mut a_spectrum = vec![Complex::zero(); transform.spectrum_len()];
+    time[..a.len()].copy_from_slice(a);
+    transform.forward(&mut time, &mut a_spectrum);
+    let mut spectrum = vec![Complex::zero(); transform.spectrum_len()];
+    time.fill(0.0);
+    time[..b.len()].copy_from_slice(b);
+    transform.forward(&mut time, &mut spectrum);
+    for (val, a_val) in spectrum.iter_mut().zip(a_spectrum.iter()) {
+        *val *= *a_val;
+    }
+    transform.inverse(&mut spectrum, &mut time);
+    time.truncate(combined_len);
+    for sample in time.iter_mut() {
+        *sample /= padded_window_size as f64;
+    }
+    time
+}
+
+/// Resamples an impulse response recorded at `from_hz` for use at `to_hz`
+///
+/// Every output sample is interpolated with a Blackman-windowed sinc spanning 32
+///  zero crossings on each side, whose cutoff is lowered to the new Nyquist
+///  frequency when downsampling, so that the result is band-limited without
+///  audible aliasing or imaging; only the last few percent of the band below
+///  Nyquist are attenuated. The IR is also scaled by `from_hz / to_hz`, so that
+///  it keeps the same gain when convolving at the new rate, and its length is
+///  scaled by `to_hz / from_hz`, rounded up.
+pub fn resample_ir(ir: &[f64], from_hz: u32, to_hz: u32) -> Vec<f64> {
+    assert!(from_hz > 0 && to_hz > 0, "sample rates must be greater than 0");
+    if from_hz == to_hz {
+        return ir.to_vec();
+    }
+    const ZERO_CROSSINGS: f64 = 32.0;
+    let ratio = from_hz as f64 / to_hz as f64;
+    // The cutoff relative to the input's Nyquist frequency
+    let cutoff = ratio.recip().min(1.0);
+    let half_width = ZERO_CROSSINGS / cutoff;
+    let output_len = (ir.len() as u64 * to_hz as u64).div_ceil(from_hz as u64) as usize;
+    (0..output_len).map(|n| {
+        let position = n as f64 * ratio;
+        let first = (position - half_width).ceil().max(0.0) as usize;
+        let last = ((position + half_width).floor() as usize).min(ir.len().saturating_sub(1));
+        let sum = (first..=last).fold(0.0, |acc, k| {
+            let x = position - k as f64;
+            let sinc = if x == 0.0 { 1.0 } else { (PI * cutoff * x).sin() / (PI * cutoff * x) };
+            let phase = PI * x / half_width;
+            let window = 0.42 + 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos();
+            acc + ir[k] * cutoff * sinc * window
+        });
+        sum * ratio
+    }).collect()
+}
+
+/// Converts an impulse response to minimum phase, keeping its magnitude response
+///
+/// This uses the cepstral method: the real cepstrum of the IR, the inverse FFT of
+///  its log magnitude, is folded onto positive quefrencies, which makes it
+///  causal, then turned back into a spectrum with an FFT and exponentiated, and
+///  the result is transformed back to the time domain. The FFTs are zero-padded
+///  to 8 times the length of the IR to keep the aliasing of the cepstrum low,
+///  and the magnitude is floored 240 dB below its peak so that spectral nulls
+///  stay finite. The result has the same length as `ir`, with its energy moved
+///  as early as possible, so transients are no longer smeared by pre-ringing.
+pub fn to_minimum_phase(ir: &[f64]) -> Vec<f64> {
+    if ir.is_empty() {
+        return Vec::new();
+    }
+    let fft_size = (ir.len() * 8).next_power_of_two();
+    let mut planner = rustfft::FftPlanner::new();
+    let forward = planner.plan_fft_forward(fft_size);
+    let inverse = planner.plan_fft_inverse(fft_size);
+    let mut buffer = vec![Complex::zero(); fft_size];
+    for (bin, sample) in buffer.iter_mut().zip(ir.iter()) {
+        *bin = Complex::new(*sample, 0.0);
+    }
+    forward.process(&mut buffer);
+    let floor = buffer.iter().map(|bin| bin.norm()).fold(0.0, f64::max) * 1e-12;
+    for bin in buffer.iter_mut() {
+        *bin = Complex::new(bin.norm().max(floor).max(f64::MIN_POSITIVE).ln(), 0.0);
+    }
+    inverse.process(&mut buffer);
+    // Fold the cepstrum: keep quefrency 0 and Nyquist, double the positive
+    //  quefrencies and drop the negative ones
+    let scale = 1.0 / fft_size as f64;
+    for (i, bin) in buffer.iter_mut().enumerate() {
+        let weight = if i == 0 || i == fft_size / 2 {
+            1.0
+        } else if i < fft_size / 2 {
+            2.0
+        } else {
+            0.0
+        };
+        *bin = Complex::new(bin.re * scale * weight, 0.0);
+    }
+    forward.process(&mut buffer);
+    for bin in buffer.iter_mut() {
+        *bin = bin.exp();
+    }
+    inverse.process(&mut buffer);
+    buffer.iter().take(ir.len()).map(|bin| bin.re * scale).collect()
+}
+
+/// How `FFTConvolution::new_normalized` scales an impulse response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalizeMode {
+    /// Leave the IR as is
+    #[default]
+    None,
+    /// Scale by `1 / max(|h[n]|)` so the largest sample has a magnitude of 1
+    Peak,
+    /// Scale by `1 / sqrt(sum(h[n]^2))` so the IR has unit L2 norm (unit energy)
+    Energy,
+}
+impl NormalizeMode {
+    /// The factor the IR is multiplied by, which is 1 for `None` and for an IR
+    ///  that is all zeros
+    pub fn factor<T>(&self, ir: &[T]) -> T where T: Float {
+        let norm = match self {
+            NormalizeMode::None => return T::one(),
+            NormalizeMode::Peak => ir.iter().fold(T::zero(), |peak, sample| peak.max(sample.abs())),
+            NormalizeMode::Energy => ir.iter().fold(T::zero(), |energy, sample| energy + *sample * *sample).sqrt(),
+        };
+        if norm > T::zero() {
+            T::one() / norm
+        } else {
+            T::one()
+        }
+    }
 }
 
-pub struct FFTConvolution {
-    x: RingBuffer<Complex<f64>>,
-    out: RingBuffer<f64>,
+/// The magnitude below which `FFTConvolution::set_flush_denormals` flushes output
+///  samples to zero
+pub const DENORMAL_THRESHOLD: f64 = 1e-15;
+
+pub type FFTConvolution64 = FFTConvolution<f64>;
+pub type FFTConvolution32 = FFTConvolution<f32>;
+
+pub struct FFTConvolution<T = f64> where T: FftNum + Float {
+    x: RingBuffer<T>,
+    out: RingBuffer<T>,
     window_size: usize,
-    ir: Vec<f64>,
-    ir_fft_cache: Vec<Complex<f64>>,
-    fft_planner: Arc<Mutex<FftPlanner<f64>>>,
+    ir: Vec<T>,
+    /// Shared between filters built from the same IR, and only written through
+    ///  `update_ir_fft_cache`, which never modifies a spectrum other filters use
+    ir_fft_cache: Arc<Vec<Complex<T>>>,
+    fft_planner: Arc<Mutex<Planner<T>>>,
+    transform: Transform<T>,
+    fft_buffer: Vec<T>,
+    spectrum: Vec<Complex<T>>,
+    crossfade: Option<Crossfade<T>>,
+    dry: RingBuffer<T>,
+    mix: T,
+    gain: GainRamp<T>,
+    bypass: bool,
+    flush_denormals: bool,
+    /// The threshold of `set_spectral_gate` in dB, if the gate is on
+    spectral_gate: Option<f64>,
+    meter: RmsMeter<T>,
+    /// Delays the convolved signal, as if the IR started with this many zeros
+    predelay: RingBuffer<T>,
+    /// The first `window_size` taps of the IR in zero-latency mode, convolved
+    ///  directly while the FFT only handles the rest of `ir`
+    head: Option<DirectConvolution<T>>,
+    freeze: bool,
+    clip: bool,
+    /// Latched whenever an output sample is clipped, until `reset_clipping`
+    clipped: bool,
+    /// The input of the last full chunk, which is fed in again and again while
+    ///  frozen
+    last_chunk: Vec<T>,
 }
 
-impl FFTConvolution {
-    pub fn new(ir: Vec<f64>, window_size: usize) -> FFTConvolution {
-        let padded_window_size = Self::padded_window_size(ir.len(), window_size);
-        let mut ir_fft_cache: Vec<Complex<f64>> = ir.iter().map(|sample| Complex::new(*sample, 0.0)).chain(iter::repeat(Complex::zero()).take(padded_window_size - ir.len())).collect();
-        let fft_planner = Arc::new(Mutex::new(FftPlanner::new()));
-        {
-            let fft = fft_planner.lock().unwrap().plan_fft_forward(padded_window_size);
-            fft.process(&mut ir_fft_cache);
+/// Accumulates the output's sum of squares over blocks of `window_size` samples
+#[derive(Clone, Copy)]
+struct RmsMeter<T> where T: Float {
+    sum: T,
+    count: usize,
+    rms: T,
+}
+impl<T> RmsMeter<T> where T: Float {
+    fn new() -> Self {
+        RmsMeter {
+            sum: T::zero(),
+            count: 0,
+            rms: T::zero(),
         }
-        FFTConvolution {
+    }
+    fn add(&mut self, sample: T, block_size: usize) {
+        self.sum = self.sum + sample * sample;
+        self.count += 1;
+        if self.count >= block_size {
+            self.rms = (self.sum / T::from(self.count).unwrap()).sqrt();
+            self.sum = T::zero();
+            self.count = 0;
+        }
+    }
+}
+
+/// A linear ramp of the output gain from `start` towards `target`
+///
+/// Positions are counted in samples from the front of the output buffer at the
+///  time the next chunk is convolved, so every chunk overlapping a given output
+///  sample applies the same gain to it. `current` is the unclamped gain at
+///  position 0, so that the ramp stays linear on both sides of it.
+#[derive(Clone, Copy)]
+struct GainRamp<T> where T: Float {
+    start: T,
+    current: T,
+    target: T,
+    step: T,
+    /// Output samples taken since the last chunk, for the direct head
+    elapsed: usize,
+}
+impl<T> GainRamp<T> where T: Float {
+    fn constant(gain: T) -> Self {
+        GainRamp {
+            start: gain,
+            current: gain,
+            target: gain,
+            step: T::zero(),
+            elapsed: 0,
+        }
+    }
+    fn value(&self, position: T) -> T {
+        let gain = self.current + self.step * position;
+        gain.max(self.start.min(self.target)).min(self.start.max(self.target))
+    }
+    fn at(&self, position: usize) -> T {
+        self.value(T::from(position).unwrap())
+    }
+    fn advance(&mut self, samples: usize) {
+        // The ramp is only dropped a chunk after reaching the target, as the
+        //  direct head still reads it up to `samples` before `current`
+        let reached = self.at(0) == self.target;
+        self.current = self.current + self.step * T::from(samples).unwrap();
+        if reached {
+            self.current = self.target;
+            self.step = T::zero();
+        }
+        self.elapsed = 0;
+    }
+    /// The gain of the output sample being taken now, which sits `window_size`
+    ///  samples before the position it had when the last chunk was convolved
+    fn next_output(&mut self, window_size: usize) -> T {
+        let gain = self.value(T::from(self.elapsed).unwrap() - T::from(window_size).unwrap());
+        self.elapsed += 1;
+        gain
+    }
+}
+
+/// The state of the outgoing IR while `set_ir_crossfade` blends it out
+struct Crossfade<T> where T: FftNum + Float {
+    transform: Transform<T>,
+    ir_fft_cache: Arc<Vec<Complex<T>>>,
+    fft_buffer: Vec<T>,
+    spectrum: Vec<Complex<T>>,
+    out: RingBuffer<T>,
+    head: Option<DirectConvolution<T>>,
+    /// The new IR only produces output once it has convolved a chunk, so the fade
+    ///  is held until then
+    started: bool,
+    position: usize,
+    length: usize,
+}
+
+impl<T> FFTConvolution<T> where T: FftNum + Float {
+    /// Creates the filter, panicking where `try_new` would return an error
+    pub fn new(ir: Vec<T>, window_size: usize) -> FFTConvolution<T> {
+        Self::try_new(ir, window_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking that `ir` is non-empty, that `window_size` is
+    ///  not 0 and that the resulting `padded_window_size` does not overflow
+    pub fn try_new(ir: Vec<T>, window_size: usize) -> Result<FFTConvolution<T>, ConvolutionError> {
+        Self::checked_padded_window_size(ir.len(), window_size)?;
+        Ok(Self::with_cache(ir, window_size, Arc::new(Mutex::new(Planner::new())), None))
+    }
+    /// Creates the filter from a spectrum previously obtained from `ir_spectrum`,
+    ///  skipping the forward FFT of the IR
+    ///
+    /// `ir_fft_cache` must be the spectrum of an `ir_len`-sample IR for this
+    ///  `window_size`, and so have one bin per bin of the `padded_window_size` FFT
+    ///  the filter uses, or `ConvolutionError::LengthMismatch` is returned. The
+    ///  time-domain IR is recovered with one inverse FFT, as it is needed again if
+    ///  the window size changes.
+    pub fn from_spectrum(ir_fft_cache: Vec<Complex<T>>, ir_len: usize, window_size: usize) -> Result<FFTConvolution<T>, ConvolutionError> {
+        Self::from_shared(Arc::new(ir_fft_cache), ir_len, window_size, Arc::new(Mutex::new(Planner::new())))
+    }
+    /// Same as `from_spectrum`, but shares `ir_fft_cache` and `fft_planner` with
+    ///  every other filter built from them, for instance the voices of a sampler
+    ///  that all use the same IR
+    ///
+    /// Get the spectrum and planner from the first filter with `shared_ir_spectrum`
+    ///  and `fft_planner`. Each filter then only holds its own input and output
+    ///  buffers, scratch space and a copy of the time-domain IR, while the spectrum
+    ///  is stored once and the FFTs are planned once.
+    ///
+    /// Note
+    /// ====
+    /// A shared spectrum is never written to. A filter that needs a different
+    ///  spectrum, after `set_ir`, `set_ir_crossfade` or a `set_window_size` that
+    ///  changes `fft_size()`, allocates its own and stops sharing, leaving the
+    ///  other filters unaffected.
+    pub fn from_shared(ir_fft_cache: Arc<Vec<Complex<T>>>, ir_len: usize, window_size: usize, fft_planner: Arc<Mutex<Planner<T>>>) -> Result<FFTConvolution<T>, ConvolutionError> {
+        let padded_window_size = Self::checked_padded_window_size(ir_len, window_size)?;
+        let expected = engine::spectrum_len(padded_window_size);
+        if ir_fft_cache.len() != expected {
+            return Err(ConvolutionError::LengthMismatch { expected, found: ir_fft_cache.len() });
+        }
+        let mut transform = Transform::new(&fft_planner, padded_window_size);
+        let mut spectrum = ir_fft_cache.to_vec();
+        let mut ir = vec![T::zero(); padded_window_size];
+        transform.inverse(&mut spectrum, &mut ir);
+        ir.truncate(ir_len);
+        let scale = T::from_usize(padded_window_size).unwrap();
+        for sample in ir.iter_mut() {
+            *sample = *sample / scale;
+        }
+        Ok(Self::with_cache(ir, window_size, fft_planner, Some(ir_fft_cache)))
+    }
+    /// Creates the filter after scaling `ir` according to `mode`
+    ///
+    /// Normalizing changes the perceived loudness of the output: the IR is
+    ///  multiplied by exactly `mode.factor(&ir)`, see `NormalizeMode` for the
+    ///  factor each mode uses.
+    pub fn new_normalized(mut ir: Vec<T>, window_size: usize, mode: NormalizeMode) -> FFTConvolution<T> {
+        let factor = mode.factor(&ir);
+        for sample in ir.iter_mut() {
+            *sample = *sample * factor;
+        }
+        Self::new(ir, window_size)
+    }
+    /// Creates the filter with `predelay_samples` of silence before the IR
+    ///
+    /// This sounds the same as prepending that many zeros to `ir`, but the FFT
+    ///  stays sized for `ir` alone and the convolved signal is instead run through
+    ///  a delay line of `predelay_samples`. The dry signal of `set_mix` is not
+    ///  delayed any further, and `latency()` still only counts the window size.
+    pub fn with_predelay(ir: Vec<T>, predelay_samples: usize, window_size: usize) -> FFTConvolution<T> {
+        let mut convolution = Self::new(ir, window_size);
+        convolution.predelay = RingBuffer::new(predelay_samples).initialize(T::zero());
+        convolution
+    }
+    /// Creates the filter from at most the first `max_ir_len` samples of `ir`
+    ///
+    /// The FFT size, and with it the memory held by the filter, grows with the IR
+    ///  length, so capping it bounds memory at the cost of losing the end of the
+    ///  tail. When the IR is actually truncated its last `max_ir_len / 8` samples
+    ///  are faded out with a half-cosine so the tail doesn't end in a hard cut, see
+    ///  `new_truncated_with_fade` to choose the fade length.
+    pub fn new_truncated(ir: Vec<T>, window_size: usize, max_ir_len: usize) -> FFTConvolution<T> {
+        Self::new_truncated_with_fade(ir, window_size, max_ir_len, max_ir_len / 8)
+    }
+    /// Same as `new_truncated`, but fading out the last `fade_len` samples (at most
+    ///  `max_ir_len`) of a truncated IR, a `fade_len` of 0 cutting it as is
+    pub fn new_truncated_with_fade(mut ir: Vec<T>, window_size: usize, max_ir_len: usize, fade_len: usize) -> FFTConvolution<T> {
+        if ir.len() > max_ir_len {
+            ir.truncate(max_ir_len);
+            let fade_len = fade_len.min(max_ir_len);
+            let fade_start = max_ir_len - fade_len;
+            let pi = T::from(std::f64::consts::PI).unwrap();
+            let half = T::from(0.5).unwrap();
+            for (k, sample) in ir[fade_start..].iter_mut().enumerate() {
+                let phase = T::from(k + 1).unwrap() / T::from(fade_len + 1).unwrap();
+                *sample = *sample * half * (T::one() + (pi * phase).cos());
+            }
+        }
+        Self::new(ir, window_size)
+    }
+    /// Creates the filter in zero-latency mode, panicking where `try_new` would
+    ///  return an error
+    ///
+    /// The first `window_size` taps of the IR are convolved directly in the time
+    ///  domain and the FFT only handles the remaining taps, whose contribution
+    ///  starts `window_size` samples late anyway, so `latency()` is 0. The output
+    ///  is the same as that of `new` without its `window_size` samples of latency,
+    ///  at the cost of a dot product over the `window_size` head taps per sample.
+    ///  `ir_spectrum()` is then the spectrum of the tail alone.
+    ///
+    /// Note
+    /// ====
+    /// A `set_gain_smoothed` ramp starts at the same output sample as it would with
+    ///  `new`, the next chunk boundary, and runs over this filter's own
+    ///  `internal_buffer_size()`, which only covers the tail. As the output is not
+    ///  delayed, the ramp falls on the signal `window_size` samples later than on a
+    ///  latency-aligned `new` filter, so the two only match for a constant gain.
+    pub fn new_zero_latency(ir: Vec<T>, window_size: usize) -> FFTConvolution<T> {
+        Self::checked_padded_window_size(ir.len(), window_size).unwrap_or_else(|err| panic!("{}", err));
+        let head_len = window_size.min(ir.len());
+        // The tail is empty for an IR no longer than the window
+        Self::with_cache(ir[head_len..].to_vec(), window_size, Arc::new(Mutex::new(Planner::new())), None).with_head(ir)
+    }
+    /// Creates the filter using an existing planner, so that several filters
+    ///  with the same `padded_window_size` only plan their FFTs once
+    ///
+    /// Panics where `try_new` would return an error.
+    pub fn with_planner(ir: Vec<T>, window_size: usize, fft_planner: Arc<Mutex<Planner<T>>>) -> FFTConvolution<T> {
+        Self::checked_padded_window_size(ir.len(), window_size).unwrap_or_else(|err| panic!("{}", err));
+        Self::with_cache(ir, window_size, fft_planner, None)
+    }
+    /// Creates the filter reusing `ir_fft_cache` as the spectrum of `ir` if it is
+    ///  given, instead of computing it
+    fn with_cache(ir: Vec<T>, window_size: usize, fft_planner: Arc<Mutex<Planner<T>>>, ir_fft_cache: Option<Arc<Vec<Complex<T>>>>) -> FFTConvolution<T> {
+        let padded_window_size = Self::padded_window_size(ir.len(), window_size);
+        let transform = Transform::new(&fft_planner, padded_window_size);
+        let computed = ir_fft_cache.is_none();
+        let mut convolution = FFTConvolution {
             x: RingBuffer::new(window_size),
-            out: RingBuffer::new(padded_window_size).initialize(0.0),
+            out: RingBuffer::new(padded_window_size).initialize(T::zero()),
             window_size,
             ir,
-            ir_fft_cache,
+            ir_fft_cache: ir_fft_cache.unwrap_or_else(|| Arc::new(vec![Complex::zero(); transform.spectrum_len()])),
+            spectrum: vec![Complex::zero(); transform.spectrum_len()],
             fft_planner,
+            transform,
+            fft_buffer: vec![T::zero(); padded_window_size],
+            crossfade: None,
+            dry: RingBuffer::new(window_size).initialize(T::zero()),
+            mix: T::one(),
+            gain: GainRamp::constant(T::one()),
+            bypass: false,
+            flush_denormals: false,
+            spectral_gate: None,
+            meter: RmsMeter::new(),
+            predelay: RingBuffer::new(0),
+            head: None,
+            freeze: false,
+            clip: false,
+            clipped: false,
+            last_chunk: vec![T::zero(); window_size],
+        };
+        if computed {
+            convolution.update_ir_fft_cache();
+        }
+        convolution
+    }
+    /// Creates a filter with the same IR, window size and planner that shares this
+    ///  filter's IR spectrum instead of computing its own, with fresh default state
+    fn sharing_ir(&self) -> FFTConvolution<T> {
+        let head_len = self.head_len(self.ir.len());
+        let convolution = Self::with_cache(self.ir[head_len..].to_vec(), self.window_size, Arc::clone(&self.fft_planner), Some(Arc::clone(&self.ir_fft_cache)));
+        if self.head.is_some() {
+            convolution.with_head(self.ir.clone())
+        } else {
+            convolution
+        }
+    }
+    /// Switches a filter built for the tail of `ir` to zero-latency mode,
+    ///  convolving the head of `ir` directly
+    fn with_head(mut self, ir: Vec<T>) -> FFTConvolution<T> {
+        let head_len = self.window_size.min(ir.len());
+        self.head = Some(DirectConvolution::new(ir[..head_len].to_vec()));
+        self.ir = ir;
+        self.dry = RingBuffer::new(0);
+        self
+    }
+    /// How many taps of an `ir_len`-sample IR are convolved directly, which is
+    ///  0 unless the filter is in zero-latency mode
+    fn head_len(&self, ir_len: usize) -> usize {
+        match self.head {
+            Some(_) => self.window_size.min(ir_len),
+            None => 0,
+        }
+    }
+    /// Replaces the impulse response
+    ///
+    /// When the new IR leads to the same `padded_window_size` its spectrum is
+    ///  computed in place, reusing the existing FFT plan and buffers. Otherwise the
+    ///  FFT is replanned and the buffers are reallocated. In both cases the samples
+    ///  already buffered in the input and output are kept, so the stream continues
+    ///  without discontinuity beyond the change of IR itself. Panics where
+    ///  `try_set_ir` would return an error.
+    pub fn set_ir(&mut self, ir: Vec<T>) {
+        self.try_set_ir(ir).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Same as `set_ir`, but checks the new IR as `try_new` does, leaving the
+    ///  filter untouched if it is rejected
+    pub fn try_set_ir(&mut self, ir: Vec<T>) -> Result<(), ConvolutionError> {
+        Self::checked_padded_window_size(ir.len(), self.window_size)?;
+        let head_len = self.head_len(ir.len());
+        let padded_window_size = Self::padded_window_size(ir.len() - head_len, self.window_size);
+        if let Some(head) = &mut self.head {
+            head.set_ir(ir[..head_len].to_vec());
         }
+        self.ir = ir;
+        if padded_window_size != self.transform.len() {
+            self.transform = Transform::new(&self.fft_planner, padded_window_size);
+            self.ir_fft_cache = Arc::new(vec![Complex::zero(); self.transform.spectrum_len()]);
+            self.spectrum = vec![Complex::zero(); self.transform.spectrum_len()];
+            self.fft_buffer = vec![T::zero(); padded_window_size];
+            // Never shrink the output below its pending tail, so no audio is lost
+            let mut out: Vec<T> = self.out.empty();
+            let out_len = out.len().max(padded_window_size);
+            out.resize(out_len, T::zero());
+            self.out = RingBuffer::from(out);
+        }
+        self.update_ir_fft_cache();
+        Ok(())
+    }
+    /// Changes the window size, which is also the latency
+    ///
+    /// The samples already buffered in the input are convolved first so no audio
+    ///  is lost, then the input buffer is rebuilt and the FFT replanned for the new
+    ///  `padded_window_size`, keeping the IR and the pending output. A running
+    ///  crossfade is completed immediately. This allocates and replans, so it is
+    ///  not real-time-safe and is meant for reconfiguring between streams.
+    ///
+    /// In zero-latency mode the IR is split again at the new window size, which
+    ///  is not seamless: the output stays continuous, but the samples already
+    ///  convolved are not moved between the head and the tail. Panics where
+    ///  `try_set_window_size` would return an error.
+    pub fn set_window_size(&mut self, window_size: usize) {
+        self.try_set_window_size(window_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Same as `set_window_size`, but checks the new window size as `try_new`
+    ///  does, leaving the filter untouched if it is rejected
+    pub fn try_set_window_size(&mut self, window_size: usize) -> Result<(), ConvolutionError> {
+        Self::checked_padded_window_size(self.ir.len(), window_size)?;
+        if !self.x.is_empty() {
+            self.convolve_chunk();
+        }
+        self.crossfade = None;
+        self.window_size = window_size;
+        let head_len = self.head_len(self.ir.len());
+        let padded_window_size = Self::padded_window_size(self.ir.len() - head_len, window_size);
+        if let Some(head) = &mut self.head {
+            head.set_ir(self.ir[..head_len].to_vec());
+        }
+        self.x.set_capacity(window_size);
+        self.last_chunk.resize(window_size, T::zero());
+        if padded_window_size != self.transform.len() {
+            self.transform = Transform::new(&self.fft_planner, padded_window_size);
+            self.ir_fft_cache = Arc::new(vec![Complex::zero(); self.transform.spectrum_len()]);
+            self.spectrum = vec![Complex::zero(); self.transform.spectrum_len()];
+            self.fft_buffer = vec![T::zero(); padded_window_size];
+            let mut out: Vec<T> = self.out.empty();
+            let out_len = out.len().max(padded_window_size);
+            out.resize(out_len, T::zero());
+            self.out = RingBuffer::from(out);
+            self.update_ir_fft_cache();
+        } else if self.head.is_some() {
+            self.update_ir_fft_cache();
+        }
+        // The pending dry samples are kept and the delay line is padded or truncated
+        //  at the back to the new latency
+        let mut dry: Vec<T> = self.dry.empty();
+        dry.resize(self.latency(), T::zero());
+        self.dry = RingBuffer::from(dry);
+        Ok(())
+    }
+    /// Replaces the impulse response, linearly crossfading from the old IR to the
+    ///  new one over `fade_blocks` blocks of `window_size` samples
+    ///
+    /// The fade starts once the new IR has convolved its first chunk, at most
+    ///  `window_size` samples later. During the fade every chunk is convolved with
+    ///  both IRs, so the cost of `compute` doubles until the old IR, its spectrum
+    ///  and its tail are dropped at the end of the fade. Starting another crossfade
+    ///  while one is running drops the oldest IR immediately. A `fade_blocks` of 0
+    ///  is the same as `set_ir`. Panics where `try_set_ir_crossfade` would return
+    ///  an error.
+    pub fn set_ir_crossfade(&mut self, ir: Vec<T>, fade_blocks: usize) {
+        self.try_set_ir_crossfade(ir, fade_blocks).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Same as `set_ir_crossfade`, but checks the new IR as `try_new` does,
+    ///  leaving the filter untouched if it is rejected
+    pub fn try_set_ir_crossfade(&mut self, ir: Vec<T>, fade_blocks: usize) -> Result<(), ConvolutionError> {
+        Self::checked_padded_window_size(ir.len(), self.window_size)?;
+        if fade_blocks == 0 {
+            self.crossfade = None;
+            return self.try_set_ir(ir);
+        }
+        let head_len = self.head_len(ir.len());
+        let padded_window_size = Self::padded_window_size(ir.len() - head_len, self.window_size);
+        let transform = Transform::new(&self.fft_planner, padded_window_size);
+        let spectrum_len = transform.spectrum_len();
+        let old_head = self.head.clone();
+        if let Some(head) = &mut self.head {
+            head.set_ir(ir[..head_len].to_vec());
+        }
+        self.crossfade = Some(Crossfade {
+            transform: std::mem::replace(&mut self.transform, transform),
+            ir_fft_cache: std::mem::replace(&mut self.ir_fft_cache, Arc::new(vec![Complex::zero(); spectrum_len])),
+            fft_buffer: std::mem::replace(&mut self.fft_buffer, vec![T::zero(); padded_window_size]),
+            spectrum: std::mem::replace(&mut self.spectrum, vec![Complex::zero(); spectrum_len]),
+            out: std::mem::replace(&mut self.out, RingBuffer::new(padded_window_size).initialize(T::zero())),
+            head: old_head,
+            started: false,
+            position: 0,
+            length: fade_blocks * self.window_size,
+        });
+        self.ir = ir;
+        self.update_ir_fft_cache();
+        Ok(())
+    }
+    /// Sets the dry/wet balance, from 0.0 (only the input) to 1.0 (only the
+    ///  convolved signal, the default)
+    ///
+    /// The dry signal is delayed by `latency()` samples, so it stays time-aligned
+    ///  with the convolved signal.
+    pub fn set_mix(&mut self, wet: T) {
+        self.mix = wet.max(T::zero()).min(T::one());
+    }
+    pub fn mix(&self) -> T {
+        self.mix
+    }
+    /// Bypasses the convolution while keeping the output delayed by `latency()`
+    ///  samples
+    ///
+    /// The input keeps going through the convolution, so switching back is
+    ///  seamless, but the output is the delayed dry signal. This keeps A/B
+    ///  comparisons phase-aligned.
+    pub fn set_bypass(&mut self, bypass: bool) {
+        self.bypass = bypass;
+    }
+    pub fn is_bypassed(&self) -> bool {
+        self.bypass
+    }
+    /// Flushes every sample of the output buffer whose magnitude falls below
+    ///  `DENORMAL_THRESHOLD` to zero after each chunk is accumulated, off by default
+    ///
+    /// Reverb tails decay towards denormal floats, which are very slow to compute
+    ///  with on most CPUs. Flushing cuts the tail once it is about 300 dB down, which
+    ///  slightly alters it but avoids the CPU spikes.
+    pub fn set_flush_denormals(&mut self, enabled: bool) {
+        self.flush_denormals = enabled;
+    }
+    pub fn flushes_denormals(&self) -> bool {
+        self.flush_denormals
+    }
+    /// Zeroes every bin of each convolved chunk's spectrum whose magnitude falls
+    ///  below `threshold_db`, before the inverse FFT, off by default
+    ///
+    /// The magnitude is that of the input spectrum multiplied by the IR spectrum,
+    ///  in dB relative to a full-scale sine filling the whole chunk, which peaks at
+    ///  0 dB. This gates out the noise floor of a measured IR, or a reverb tail once
+    ///  it has decayed, at the cost of some spectral smearing at the threshold.
+    pub fn set_spectral_gate(&mut self, threshold_db: f64) {
+        self.spectral_gate = Some(threshold_db);
+    }
+    pub fn disable_spectral_gate(&mut self) {
+        self.spectral_gate = None;
+    }
+    /// The threshold of the spectral gate in dB, or `None` if it is off
+    pub fn spectral_gate(&self) -> Option<f64> {
+        self.spectral_gate
+    }
+    /// Freezes the convolution, sustaining its current tail indefinitely
+    ///
+    /// While frozen the input is ignored, and the `window_size()` input samples of
+    ///  the last full chunk are fed in again in a loop instead, so the output
+    ///  settles into the periodic response of the convolution to that block rather
+    ///  than decaying. Its level is that of the IR excited by a steady input, which
+    ///  can be louder than the tail was when freezing. Freezing before the first
+    ///  chunk has been convolved sustains silence. The dry signal of `set_mix` is
+    ///  frozen as well. Unfreezing resumes with the live input at the next sample,
+    ///  and `flush` drains the output as if the input had stopped either way.
+    pub fn set_freeze(&mut self, freeze: bool) {
+        self.freeze = freeze;
+    }
+    pub fn is_frozen(&self) -> bool {
+        self.freeze
+    }
+    /// Hard-clips every output sample to `-1.0..=1.0`, off by default
+    ///
+    /// The clipping is applied last, after the dry/wet mix.
+    pub fn set_output_clip(&mut self, enabled: bool) {
+        self.clip = enabled;
+    }
+    pub fn clips_output(&self) -> bool {
+        self.clip
+    }
+    /// Whether any output sample has been clipped since the filter was created or
+    ///  `reset_clipping` was last called
+    pub fn had_clipping(&self) -> bool {
+        self.clipped
+    }
+    /// Lowers the flag read by `had_clipping`
+    pub fn reset_clipping(&mut self) {
+        self.clipped = false;
+    }
+    /// The RMS level of the output over the most recently completed block of
+    ///  `window_size()` samples
+    ///
+    /// The value only updates once every `window_size()` output samples, and is 0
+    ///  until the first block has been output.
+    pub fn output_rms(&self) -> T {
+        self.meter.rms
+    }
+    /// Sets the linear output gain, 1.0 by default
+    ///
+    /// The gain is applied as each chunk is accumulated into the output buffer,
+    ///  so a change is heard from the next chunk on. This steps the gain at once,
+    ///  see `set_gain_smoothed` to automate it without zipper noise.
+    pub fn set_gain(&mut self, gain: T) {
+        self.gain = GainRamp::constant(gain);
+    }
+    /// Ramps the linear output gain to `gain` over `internal_buffer_size()`
+    ///  samples
+    ///
+    /// The ramp is linear per sample and starts with the next chunk, continuing
+    ///  from wherever a previous ramp had got to.
+    pub fn set_gain_smoothed(&mut self, gain: T) {
+        let ramp_len = T::from(self.internal_buffer_size().max(1)).unwrap();
+        let current = self.gain.at(0);
+        self.gain.start = current;
+        self.gain.current = current;
+        self.gain.step = (gain - current) / ramp_len;
+        self.gain.target = gain;
+    }
+    /// The gain last set, which is where a running `set_gain_smoothed` ramp ends
+    pub fn gain(&self) -> T {
+        self.gain.target
+    }
+    /// Whether a crossfade started by `set_ir_crossfade` is still running
+    pub fn is_crossfading(&self) -> bool {
+        self.crossfade.is_some()
     }
     pub fn window_size(&self) -> usize {
         self.window_size
     }
-    pub fn output_buffer(&self) -> &RingBuffer<f64> {
+    /// How many more samples `compute` needs before the next chunk is convolved,
+    ///  from `window_size()` right after a chunk down to 1
+    pub fn samples_until_block(&self) -> usize {
+        self.window_size - self.x.len()
+    }
+    /// The delay in samples between a sample entering `compute` and its
+    ///  contribution first appearing in the output, 0 in zero-latency mode
+    pub fn latency(&self) -> usize {
+        match self.head {
+            Some(_) => 0,
+            None => self.window_size,
+        }
+    }
+    pub fn output_buffer(&self) -> &RingBuffer<T> {
         &self.out
     }
     pub fn internal_buffer_size(&self) -> usize {
         self.out.len()
     }
+    pub fn ir_len(&self) -> usize {
+        self.ir.len()
+    }
+    /// The IR currently in use, after any normalization or truncation
+    ///
+    /// `ir_spectrum()` is its spectrum, except in zero-latency mode, where it is
+    ///  the spectrum of the tail after the first `window_size()` taps alone.
+    pub fn ir(&self) -> &[T] {
+        &self.ir
+    }
+    /// The number of samples of silence `with_predelay` put before the IR
+    pub fn predelay(&self) -> usize {
+        self.predelay.capacity()
+    }
+    pub fn fft_planner(&self) -> Arc<Mutex<Planner<T>>> {
+        Arc::clone(&self.fft_planner)
+    }
+    /// The size of the FFT used to convolve each chunk, `padded_window_size`
+    pub fn fft_size(&self) -> usize {
+        self.transform.len()
+    }
+    /// The unnormalized spectrum of the zero-padded IR, as used for convolution
+    ///
+    /// For a signal sampled at `sample_rate`, bin `k` is at
+    ///  `k * sample_rate / fft_size()` Hz. With the `realfft` feature only the
+    ///  `fft_size() / 2 + 1` unique bins are stored, otherwise all `fft_size()` bins
+    ///  are, the upper half mirroring the lower one.
+    pub fn ir_spectrum(&self) -> &[Complex<T>] {
+        self.ir_fft_cache.as_slice()
+    }
+    /// The same spectrum as `ir_spectrum`, as a handle for `from_shared`
+    pub fn shared_ir_spectrum(&self) -> Arc<Vec<Complex<T>>> {
+        Arc::clone(&self.ir_fft_cache)
+    }
+    /// The magnitude `|H|` of the IR over the `fft_size() / 2 + 1` unique bins,
+    ///  from DC up to Nyquist, with bin `k` at `k * sample_rate / fft_size()` Hz
+    pub fn ir_magnitude_response(&self) -> Vec<T> {
+        let unique_bins = self.transform.len() / 2 + 1;
+        self.ir_fft_cache.iter().take(unique_bins).map(|bin| bin.norm()).collect()
+    }
+    /// Processes a whole block of samples at once
+    ///
+    /// The output is identical to calling `compute` on each sample of `input` in
+    ///  sequence, but samples are handled in runs up to the next chunk boundary.
+    pub fn process_block(&mut self, input: &[T], output: &mut [T]) {
+        self.process_runs(input, output, false);
+    }
+    /// Processes `input` of any length and returns exactly as many output samples
+    ///
+    /// The host buffer size doesn't have to match the window size: input is
+    ///  buffered across calls until a whole chunk is there, so feeding 128 samples
+    ///  at a time through a window of 4096 gives the same continuous output as
+    ///  feeding 4096, with the latency still `latency()`. This allocates the
+    ///  returned vector, use `process_block` to write into an existing buffer.
+    pub fn feed(&mut self, input: &[T]) -> Vec<T> {
+        let mut output = vec![T::zero(); input.len()];
+        self.process_block(input, &mut output);
+        output
+    }
+    /// Same as `process_block`, but adds the output onto what is already in
+    ///  `output` instead of overwriting it, so several filters can be summed into
+    ///  one bus
+    pub fn add_block(&mut self, input: &[T], output: &mut [T]) {
+        self.process_runs(input, output, true);
+    }
+    fn process_runs(&mut self, input: &[T], output: &mut [T], add: bool) {
+        assert_eq!(input.len(), output.len(), "input and output must be the same length");
+        let mut offset = 0;
+        while offset < input.len() {
+            let run = (self.window_size - self.x.len()).min(input.len() - offset);
+            let position = self.x.len();
+            for i in 0..run {
+                let signal = if self.freeze { self.last_chunk[position + i] } else { input[offset + i] };
+                let buffered_signal = self.next_output(signal);
+                let out_ref = &mut output[offset + i];
+                *out_ref = if add { *out_ref + buffered_signal } else { buffered_signal };
+            }
+            if self.freeze {
+                self.x.extend_from_slice(&self.last_chunk[position..position + run]);
+            } else {
+                self.x.extend_from_slice(&input[offset..offset + run]);
+            }
+            if self.x.len() == self.window_size {
+                self.convolve_chunk();
+            }
+            offset += run;
+        }
+    }
+    /// Processes a block of samples in place, replacing each input sample with
+    ///  the corresponding output sample
+    ///
+    /// The result is identical to that of `process_block` with separate buffers,
+    ///  so the first `latency()` samples written back on a fresh filter are the
+    ///  zeros of its priming, not the start of the convolution.
+    pub fn process_inplace(&mut self, buffer: &mut [T]) {
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let run = (self.window_size - self.x.len()).min(buffer.len() - offset);
+            let position = self.x.len();
+            if self.freeze {
+                buffer[offset..offset + run].copy_from_slice(&self.last_chunk[position..position + run]);
+            }
+            // The input is taken before it is overwritten, `next_output` never reads it
+            self.x.extend_from_slice(&buffer[offset..offset + run]);
+            for sample in buffer[offset..offset + run].iter_mut() {
+                *sample = self.next_output(*sample);
+            }
+            if self.x.len() == self.window_size {
+                self.convolve_chunk();
+            }
+            offset += run;
+        }
+    }
+    /// Same as `compute`, but returns the dry input delayed by `latency()` and the
+    ///  wet convolved signal separately, as `(dry, wet)`, to be mixed downstream
+    ///
+    /// The two are time-aligned, and left unaffected by `set_mix`, `set_bypass`
+    ///  and `set_output_clip`. The gain still applies to the wet signal only, and
+    ///  neither is counted by `output_rms`.
+    pub fn compute_split(&mut self, signal: T) -> (T, T) {
+        let signal = if self.freeze { self.last_chunk[self.x.len()] } else { signal };
+        let split = self.next_split(signal);
+
+        self.x.push_back(signal);
+        if self.x.len() == self.window_size {
+            self.convolve_chunk();
+        }
+
+        split
+    }
+    /// Same as `compute`, but also reports whether this sample completed a chunk
+    ///  and so triggered the FFT convolution, which happens once every
+    ///  `window_size()` samples
+    pub fn compute_with_status(&mut self, signal: T) -> (T, bool) {
+        let signal = if self.freeze { self.last_chunk[self.x.len()] } else { signal };
+        let buffered_signal = self.next_output(signal);
+
+        self.x.push_back(signal);
+        let convolved = self.x.len() == self.window_size;
+        if convolved {
+            self.convolve_chunk();
+        }
+
+        (buffered_signal, convolved)
+    }
+    /// Feeds `input` through the filter and discards the output
+    ///
+    /// Exactly `latency()` samples of pre-roll cancel the latency: after priming
+    ///  with the first `latency()` samples of a signal, the `n`-th following call
+    ///  to `compute`, fed sample `n + latency()` of the signal, returns sample `n`
+    ///  of its convolution. This is meant for offline rendering with look-ahead.
+    pub fn prime(&mut self, input: &[T]) {
+        for signal in input.iter() {
+            self.compute(*signal);
+        }
+    }
+    /// Retrieves the rest of the convolution after the end of a finite signal
+    ///
+    /// The partial chunk still waiting in the input buffer is convolved as if it had
+    ///  been padded with zeros, and then every remaining sample of the output is
+    ///  drained, which is `latency() + ir_len() - 1` samples, plus `predelay()`.
+    ///  Together with the samples returned by `compute`, the output is `latency()`
+    ///  samples of priming followed by the full `input.len() + ir_len() - 1` samples
+    ///  of the convolution, the predelay counting as part of the IR. Afterwards the
+    ///  filter is in the same state as if that many zeros had been fed through
+    ///  `compute`.
+    pub fn flush(&mut self) -> Vec<T> {
+        let remaining = (self.latency() + self.predelay.capacity() + self.ir.len()).saturating_sub(1);
+        if !self.x.is_empty() {
+            self.convolve_chunk();
+        }
+        // Everything left is now accumulated in the output buffer
+        (0..remaining).map(|_| self.next_output(T::zero())).collect()
+    }
+    /// Takes the next sample off the output buffer, blending in the outgoing IR
+    ///  while a crossfade is running and the delayed dry `signal`
+    fn next_output(&mut self, signal: T) -> T {
+        let (dry_signal, buffered_signal) = self.next_split(signal);
+        let output = if self.bypass {
+            dry_signal
+        } else {
+            dry_signal * (T::one() - self.mix) + buffered_signal * self.mix
+        };
+        let output = if self.clip && output.abs() > T::one() {
+            self.clipped = true;
+            output.max(-T::one()).min(T::one())
+        } else {
+            output
+        };
+        self.meter.add(output, self.window_size);
+        output
+    }
+    /// Takes the next wet sample off the output buffer along with the delayed dry
+    ///  `signal`, before they are mixed
+    fn next_split(&mut self, signal: T) -> (T, T) {
+        let mut buffered_signal = self.out.pop_front().unwrap();
+        self.out.push_back(T::zero());
+        let head_gain = match self.head {
+            Some(_) => self.gain.next_output(self.window_size),
+            None => T::zero(),
+        };
+        if let Some(head) = &mut self.head {
+            buffered_signal = buffered_signal + head.compute(signal) * head_gain;
+        }
+
+        if let Some(crossfade) = &mut self.crossfade {
+            let mut old_signal = crossfade.out.pop_front().unwrap();
+            crossfade.out.push_back(T::zero());
+            if let Some(head) = &mut crossfade.head {
+                old_signal = old_signal + head.compute(signal) * head_gain;
+            }
+            let mix = T::from_usize(crossfade.position).unwrap() / T::from_usize(crossfade.length).unwrap();
+            buffered_signal = old_signal * (T::one() - mix) + buffered_signal * mix;
+            if crossfade.started {
+                crossfade.position += 1;
+                if crossfade.position >= crossfade.length {
+                    self.crossfade = None;
+                }
+            }
+        }
+
+        let buffered_signal = self.predelay.shift(buffered_signal);
+        let dry_signal = self.dry.shift(signal);
+        (dry_signal, buffered_signal)
+    }
+    fn update_ir_fft_cache(&mut self) {
+        // `fft_buffer` is only scratch space between chunks, so it can be borrowed here
+        let fft_ir = &self.ir[self.head_len(self.ir.len())..];
+        self.fft_buffer[..fft_ir.len()].copy_from_slice(fft_ir);
+        self.fft_buffer[fft_ir.len()..].fill(T::zero());
+        // A spectrum shared with other filters is replaced rather than overwritten
+        if Arc::get_mut(&mut self.ir_fft_cache).is_none() {
+            self.ir_fft_cache = Arc::new(vec![Complex::zero(); self.transform.spectrum_len()]);
+        }
+        let ir_fft_cache = Arc::get_mut(&mut self.ir_fft_cache).unwrap();
+        self.transform.forward(&mut self.fft_buffer, ir_fft_cache);
+    }
+    /// Convolves the samples in `x` and accumulates the result into the output
+    ///
+    /// `x` is normally a full chunk, but a partial chunk of `k` samples is also
+    ///  accepted, in which case its output starts `window_size - k` samples into the
+    ///  output buffer so that it keeps the same latency as a full chunk.
+    fn convolve_chunk(&mut self) {
+        // Copy the chunk into the preallocated buffer, one memcpy per contiguous
+        //  part of the ring buffer, and zero the tail instead of going through
+        //  `buffer_back`, which would clone it
+        let chunk_len = self.x.len();
+        let offset = self.window_size - chunk_len;
+        let gain = self.gain;
+        let scale = T::one() / T::from_usize(self.transform.len()).unwrap();
+        // Compared against the squared magnitude so no square root is taken per bin
+        let gate = self.spectral_gate.map(|threshold_db| {
+            let threshold = T::from(10f64.powf(threshold_db / 20.0) * self.window_size as f64 / 2.0).unwrap();
+            threshold * threshold
+        });
+        self.x.copy_to_slice(&mut self.fft_buffer);
+        self.fft_buffer[chunk_len..].fill(T::zero());
+        if chunk_len == self.window_size {
+            self.last_chunk.copy_from_slice(&self.fft_buffer[..chunk_len]);
+        }
+        if let Some(crossfade) = &mut self.crossfade {
+            crossfade.started = true;
+            self.x.copy_to_slice(&mut crossfade.fft_buffer);
+            crossfade.fft_buffer[chunk_len..].fill(T::zero());
+            crossfade.transform.forward(&mut crossfade.fft_buffer, &mut crossfade.spectrum);
+            for (val, ir_val) in crossfade.spectrum.iter_mut().zip(crossfade.ir_fft_cache.iter()) {
+                *val = *val * *ir_val;
+            }
+            if let Some(gate) = gate {
+                Self::apply_spectral_gate(&mut crossfade.spectrum, gate);
+            }
+            crossfade.transform.inverse(&mut crossfade.spectrum, &mut crossfade.fft_buffer);
+            let old_scale = T::one() / T::from_usize(crossfade.transform.len()).unwrap();
+            for (i, (out_ref, buf_val)) in crossfade.out.inner_mut().iter_mut().skip(offset).zip(crossfade.fft_buffer.iter()).enumerate() {
+                *out_ref = *out_ref + *buf_val * old_scale * gain.at(offset + i);
+            }
+        }
+        self.x.clear();
+        self.transform.forward(&mut self.fft_buffer, &mut self.spectrum);
+        for (val, ir_val) in self.spectrum.iter_mut().zip(self.ir_fft_cache.iter()) {
+            *val = *val * *ir_val;
+        }
+        if let Some(gate) = gate {
+            Self::apply_spectral_gate(&mut self.spectrum, gate);
+        }
+        self.transform.inverse(&mut self.spectrum, &mut self.fft_buffer);
+        for (i, (out_ref, buf_val)) in self.out.inner_mut().iter_mut().skip(offset).zip(self.fft_buffer.iter()).enumerate() {
+            *out_ref = *out_ref + *buf_val * scale * gain.at(offset + i);
+        }
+        self.gain.advance(self.window_size);
+        if self.flush_denormals {
+            let threshold = T::from(DENORMAL_THRESHOLD).unwrap();
+            for out_ref in self.out.inner_mut().iter_mut() {
+                if out_ref.abs() < threshold {
+                    *out_ref = T::zero();
+                }
+            }
+            if let Some(crossfade) = &mut self.crossfade {
+                for out_ref in crossfade.out.inner_mut().iter_mut() {
+                    if out_ref.abs() < threshold {
+                        *out_ref = T::zero();
+                    }
+                }
+            }
+        }
+    }
+    /// Zeroes the bins of `spectrum` whose squared magnitude is below `gate`
+    fn apply_spectral_gate(spectrum: &mut [Complex<T>], gate: T) {
+        for val in spectrum.iter_mut() {
+            if val.norm_sqr() < gate {
+                *val = Complex::zero();
+            }
+        }
+    }
     fn padded_window_size(ir_size: usize, window_size: usize) -> usize {
         (ir_size + window_size - 1).next_power_of_two()
     }
+    fn checked_padded_window_size(ir_size: usize, window_size: usize) -> Result<usize, ConvolutionError> {
+        if ir_size == 0 {
+            return Err(ConvolutionError::EmptyIr);
+        }
+        if window_size == 0 {
+            return Err(ConvolutionError::ZeroWindow);
+        }
+        (ir_size - 1).checked_add(window_size)
+            .and_then(usize::checked_next_power_of_two)
+            .ok_or(ConvolutionError::Overflow { ir_len: ir_size, window_size })
+    }
+    pub fn builder() -> FFTConvolutionBuilder<T> {
+        FFTConvolutionBuilder::default()
+    }
+}
+
+/// Configures an `FFTConvolution` in one place
+///
+/// Everything but the window size defaults to what `FFTConvolution::new` does:
+///  no normalization, a gain of 1.0 and a fully wet mix.
+#[derive(Debug, Clone, Copy)]
+pub struct FFTConvolutionBuilder<T = f64> where T: FftNum + Float {
+    window_size: Option<usize>,
+    normalize: NormalizeMode,
+    gain: T,
+    mix: T,
+}
+impl<T> Default for FFTConvolutionBuilder<T> where T: FftNum + Float {
+    fn default() -> Self {
+        FFTConvolutionBuilder {
+            window_size: None,
+            normalize: NormalizeMode::None,
+            gain: T::one(),
+            mix: T::one(),
+        }
+    }
 }
-impl Filter for FFTConvolution {
+impl<T> FFTConvolutionBuilder<T> where T: FftNum + Float {
+    pub fn window_size(mut self, window_size: usize) -> Self {
+        self.window_size = Some(window_size);
+        self
+    }
+    pub fn normalize(mut self, mode: NormalizeMode) -> Self {
+        self.normalize = mode;
+        self
+    }
+    pub fn gain(mut self, gain: T) -> Self {
+        self.gain = gain;
+        self
+    }
+    pub fn mix(mut self, wet: T) -> Self {
+        self.mix = wet;
+        self
+    }
+    /// Creates the filter, panicking if no window size was set
+    ///
+    /// `optimal_window_size` gives a window size to start from.
+    pub fn build(self, ir: Vec<T>) -> FFTConvolution<T> {
+        let window_size = self.window_size.expect("the window size must be set before building");
+        let mut convolution = FFTConvolution::new_normalized(ir, window_size, self.normalize);
+        convolution.set_gain(self.gain);
+        convolution.set_mix(self.mix);
+        convolution
+    }
+}
+/// A signal filtered on the fly, see `FilterExt::filter_iter`
+pub struct FilteredSignal<F, I, T = f64> where F: Filter<T>, I: Iterator<Item = T> {
+    filter: F,
+    input: I,
+    skip: usize,
+    tail: Option<std::vec::IntoIter<T>>,
+}
+impl<F, I, T> Iterator for FilteredSignal<F, I, T> where F: Filter<T>, I: Iterator<Item = T>, T: Default {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        loop {
+            let output = match &mut self.tail {
+                Some(tail) => tail.next()?,
+                None => match self.input.next() {
+                    Some(signal) => self.filter.compute(signal),
+                    None => {
+                        let mut tail = self.filter.flush();
+                        if tail.is_empty() {
+                            // Makes up for the skipped priming
+                            tail = (0..self.filter.latency()).map(|_| self.filter.compute(T::default())).collect();
+                        }
+                        self.tail = Some(tail.into_iter());
+                        continue;
+                    },
+                },
+            };
+            if self.skip > 0 {
+                self.skip -= 1;
+                continue;
+            }
+            return Some(output);
+        }
+    }
+}
+
+impl<T> Filter<T> for FFTConvolution<T> where T: FftNum + Float {
+    /// Resets the filter to silence
+    ///
+    /// Afterwards the input buffer is empty and the output buffer holds exactly
+    ///  `internal_buffer_size()` zeros, so the next `internal_buffer_size()` outputs
+    ///  for a silent input are all zero. The IR, window size and all the settings
+    ///  such as gain, mix and bypass are kept.
     fn clear(&mut self) {
         self.x.clear();
-        self.out.initialize_again(0.0);
+        // Both delay lines are emptied first, as `initialize_again` only tops up
+        //  the buffer to its capacity
+        self.out.clear();
+        self.out.initialize_again(T::zero());
+        debug_assert_eq!(self.out.len(), self.out.capacity());
+        self.crossfade = None;
+        self.dry.clear();
+        self.dry.initialize_again(T::zero());
+        self.meter = RmsMeter::new();
+        self.predelay.clear();
+        self.predelay.initialize_again(T::zero());
+        self.gain.elapsed = 0;
+        self.last_chunk.fill(T::zero());
+        if let Some(head) = &mut self.head {
+            head.clear();
+        }
+    }
+    fn compute(&mut self, signal: T) -> T {
+        self.compute_with_status(signal).0
+    }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+    fn flush(&mut self) -> Vec<T> {
+        self.flush()
+    }
+}
+
+/// Time-domain FIR convolution
+///
+/// Each sample costs a dot product over the whole IR, so this is only worth it
+///  for short IRs, where it beats the FFT overhead and has no latency at all.
+#[derive(Clone)]
+pub struct DirectConvolution<T = f64> where T: Float {
+    history: RingBuffer<T>,
+    ir: Vec<T>,
+}
+impl<T> DirectConvolution<T> where T: Float {
+    pub fn new(ir: Vec<T>) -> DirectConvolution<T> {
+        DirectConvolution {
+            history: RingBuffer::new(ir.len()).initialize(T::zero()),
+            ir,
+        }
     }
-    fn compute(&mut self, signal: f64) -> f64 {
+    /// Replaces the IR, keeping the most recent input samples
+    ///
+    /// A longer IR sees the samples from before the filter was created as zeros.
+    pub fn set_ir(&mut self, ir: Vec<T>) {
+        self.history.set_capacity(ir.len());
+        self.history.fill_front(T::zero());
+        self.ir = ir;
+    }
+    pub fn ir_len(&self) -> usize {
+        self.ir.len()
+    }
+    /// Always 0, as the output for a sample is available as soon as it is computed
+    pub fn latency(&self) -> usize {
+        0
+    }
+}
+impl<T> Filter<T> for DirectConvolution<T> where T: Float {
+    fn clear(&mut self) {
+        self.history.clear();
+        self.history.initialize_again(T::zero());
+    }
+    fn compute(&mut self, signal: T) -> T {
+        self.history.push_back(signal);
+        self.history.inner().iter().rev().zip(self.ir.iter()).fold(T::zero(), |acc, (x_val, h_val)| acc + *x_val * *h_val)
+    }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+}
+
+/// The power-of-two window size that minimizes the per-sample cost of
+///  `FFTConvolution` for an IR of `ir_len` samples
+///
+/// The cost is estimated as `padded * log2(padded) / window_size`, that is one
+///  FFT of `padded_window_size` every `window_size` samples, and ties go to the
+///  smaller window for its lower latency. This ignores the latency itself, which
+///  is the window size, so it is best seen as an upper bound for offline use.
+pub fn optimal_window_size(ir_len: usize) -> usize {
+    let ir_len = ir_len.max(1);
+    let cost = |window_size: usize| {
+        let padded = (ir_len + window_size - 1).next_power_of_two() as f64;
+        padded * padded.log2() / window_size as f64
+    };
+    // Past the IR length the padded size doubles with the window and the cost
+    //  only grows, so there is no need to look further
+    let mut best = 1;
+    let mut window_size = 2;
+    while window_size <= ir_len.next_power_of_two() {
+        if cost(window_size) < cost(best) {
+            best = window_size;
+        }
+        window_size *= 2;
+    }
+    best
+}
+
+/// IRs shorter than this many taps are convolved directly by `auto_convolution`
+pub const DIRECT_CONVOLUTION_THRESHOLD: usize = 64;
+
+/// Picks `DirectConvolution` for IRs shorter than `DIRECT_CONVOLUTION_THRESHOLD`
+///  and `FFTConvolution` otherwise
+///
+/// Note that the two have different latencies: the direct path has none, while
+///  the FFT path is delayed by `window_size` samples.
+pub fn auto_convolution(ir: Vec<f64>, window_size: usize) -> Box<dyn Filter + Send> {
+    if ir.len() < DIRECT_CONVOLUTION_THRESHOLD {
+        Box::new(DirectConvolution::new(ir))
+    } else {
+        Box::new(FFTConvolution::new(ir, window_size))
+    }
+}
+
+/// Overlap-save FFT convolution
+///
+/// Instead of accumulating the tail of every chunk into an output buffer like
+///  `FFTConvolution`, this keeps a sliding history of the last
+///  `padded_window_size` input samples, convolves all of it every `window_size`
+///  samples and keeps only the `window_size` outputs that are free of circular
+///  wrap-around. The output and latency are the same as `FFTConvolution`'s.
+pub struct OverlapSaveConvolution<T = f64> where T: FftNum + Float {
+    history: RingBuffer<T>,
+    pending: usize,
+    out: RingBuffer<T>,
+    window_size: usize,
+    ir_len: usize,
+    ir_fft_cache: Vec<Complex<T>>,
+    transform: Transform<T>,
+    fft_buffer: Vec<T>,
+    spectrum: Vec<Complex<T>>,
+}
+impl<T> OverlapSaveConvolution<T> where T: FftNum + Float {
+    /// Creates the filter, panicking where `try_new` would return an error
+    pub fn new(ir: Vec<T>, window_size: usize) -> OverlapSaveConvolution<T> {
+        Self::try_new(ir, window_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking the IR and window size as
+    ///  `FFTConvolution::try_new` does
+    pub fn try_new(ir: Vec<T>, window_size: usize) -> Result<OverlapSaveConvolution<T>, ConvolutionError> {
+        let padded_window_size = FFTConvolution::<T>::checked_padded_window_size(ir.len(), window_size)?;
+        let mut transform = Transform::new(&Arc::new(Mutex::new(Planner::new())), padded_window_size);
+        let mut fft_buffer = vec![T::zero(); padded_window_size];
+        fft_buffer[..ir.len()].copy_from_slice(&ir);
+        let mut ir_fft_cache = vec![Complex::zero(); transform.spectrum_len()];
+        transform.forward(&mut fft_buffer, &mut ir_fft_cache);
+        Ok(OverlapSaveConvolution {
+            history: RingBuffer::new(padded_window_size).initialize(T::zero()),
+            pending: 0,
+            out: RingBuffer::new(window_size).initialize(T::zero()),
+            window_size,
+            ir_len: ir.len(),
+            spectrum: vec![Complex::zero(); transform.spectrum_len()],
+            ir_fft_cache,
+            transform,
+            fft_buffer,
+        })
+    }
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+    pub fn ir_len(&self) -> usize {
+        self.ir_len
+    }
+    /// The delay in samples between a sample entering `compute` and its
+    ///  contribution first appearing in the output
+    pub fn latency(&self) -> usize {
+        self.window_size
+    }
+    fn convolve_history(&mut self) {
+        self.history.copy_to_slice(&mut self.fft_buffer);
+        self.transform.forward(&mut self.fft_buffer, &mut self.spectrum);
+        for (val, ir_val) in self.spectrum.iter_mut().zip(self.ir_fft_cache.iter()) {
+            *val = *val * *ir_val;
+        }
+        self.transform.inverse(&mut self.spectrum, &mut self.fft_buffer);
+        // The first `ir_len - 1` samples are corrupted by wrap-around, and only the
+        //  last `window_size` samples belong to the new input
+        let padded_window_size = self.transform.len();
+        let scale = T::from_usize(padded_window_size).unwrap();
+        for (out_ref, buf_val) in self.out.inner_mut().iter_mut().zip(self.fft_buffer[padded_window_size - self.window_size..].iter()) {
+            *out_ref = *buf_val / scale;
+        }
+    }
+}
+impl<T> Filter<T> for OverlapSaveConvolution<T> where T: FftNum + Float {
+    fn clear(&mut self) {
+        self.history.clear();
+        self.history.initialize_again(T::zero());
+        self.pending = 0;
+        self.out.clear();
+        self.out.initialize_again(T::zero());
+    }
+    fn compute(&mut self, signal: T) -> T {
         let buffered_signal = self.out.pop_front().unwrap();
-        self.out.push_back(0.0);
+        self.out.push_back(T::zero());
 
-        if let Some(chunk) = self.x.buffer_back(Complex::new(signal, 0.0)) {
-            let window_size = chunk.len();
-            let padded_window_size = Self::padded_window_size(self.ir.len(), window_size);
-            let mut buffer: Vec<Complex<f64>> = chunk.into_iter().chain(iter::repeat(Complex::zero()).take(padded_window_size - window_size)).collect();
-            {
-                let fft = self.fft_planner.lock().unwrap().plan_fft_forward(padded_window_size);
-                fft.process(&mut buffer);
-            }
-            for (i, val) in buffer.iter_mut().enumerate() {
-                *val *= self.ir_fft_cache[i];
-            }
-            {
-                let ifft = self.fft_planner.lock().unwrap().plan_fft_inverse(padded_window_size);
-                ifft.process(&mut buffer);
-            }
-            for (out_ref, buf_val) in self.out.inner_mut().iter_mut().zip(buffer.into_iter()).take(padded_window_size) {
-                *out_ref += buf_val.re / padded_window_size as f64; //TODO: Magnitude or Real part?
-            }
+        self.history.push_back(signal);
+        self.pending += 1;
+        if self.pending == self.window_size {
+            self.pending = 0;
+            self.convolve_history();
         }
-        
+
         buffered_signal
     }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+    fn flush(&mut self) -> Vec<T> {
+        (0..self.latency() + self.ir_len() - 1).map(|_| self.compute(T::zero())).collect()
+    }
 }
-
@@ -0,0 +1,118 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{Filter, FFTConvolution};
+use crate::engine::Planner;
+
+pub trait MultiChannelFilter {
+    fn clear(&mut self);
+    /// Processes one sample frame, with one sample per channel in both `input`
+    ///  and `output`
+    fn compute(&mut self, input: &[f64], output: &mut [f64]);
+}
+
+/// Independent convolution of each channel with its own IR
+///
+/// This is the N-channel counterpart of `StereoFFTConvolution`: channel `i` of the
+///  input is only convolved with `irs[i]` and written to channel `i` of the
+///  output. All channels share one FFT planner.
+pub struct MultiChannelFFTConvolution {
+    channels: Vec<FFTConvolution>,
+}
+impl MultiChannelFFTConvolution {
+    /// Creates one filter per IR, panicking if any IR is empty or the window size
+    ///  is invalid, as `FFTConvolution::new` does
+    pub fn new(irs: Vec<Vec<f64>>, window_size: usize) -> MultiChannelFFTConvolution {
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        MultiChannelFFTConvolution {
+            channels: irs.into_iter().map(|ir| FFTConvolution::with_planner(ir, window_size, Arc::clone(&planner))).collect(),
+        }
+    }
+    pub fn channels(&self) -> usize {
+        self.channels.len()
+    }
+    pub fn window_size(&self) -> usize {
+        self.channels.first().map_or(0, |channel| channel.window_size())
+    }
+    pub fn latency(&self) -> usize {
+        self.channels.first().map_or(0, |channel| channel.latency())
+    }
+}
+impl MultiChannelFilter for MultiChannelFFTConvolution {
+    fn clear(&mut self) {
+        for channel in self.channels.iter_mut() {
+            channel.clear();
+        }
+    }
+    fn compute(&mut self, input: &[f64], output: &mut [f64]) {
+        assert_eq!(input.len(), self.channels.len(), "input must have one sample per channel");
+        assert_eq!(output.len(), self.channels.len(), "output must have one sample per channel");
+        for ((channel, signal), out_ref) in self.channels.iter_mut().zip(input.iter()).zip(output.iter_mut()) {
+            *out_ref = channel.compute(*signal);
+        }
+    }
+}
+
+/// An `M`-input, `N`-output convolution matrix
+///
+/// `irs[i][j]` is the response of output `j` to input `i`, so
+///  `output[j] = sum_i conv(input[i], irs[i][j])`. This generalizes
+///  `TrueStereoFFTConvolution` to arbitrary routing, e.g. Ambisonics decoding.
+///  Cells with an empty IR contribute nothing and are skipped entirely.
+pub struct ConvolutionMatrix {
+    /// Indexed `[in][out]`, with `None` for the empty cells
+    cells: Vec<Vec<Option<FFTConvolution>>>,
+    outputs: usize,
+    window_size: usize,
+}
+impl ConvolutionMatrix {
+    pub fn new(irs: Vec<Vec<Vec<f64>>>, window_size: usize) -> ConvolutionMatrix {
+        let outputs = irs.first().map_or(0, |row| row.len());
+        assert!(irs.iter().all(|row| row.len() == outputs), "every input must have an IR for each output");
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        let cells = irs.into_iter().map(|row| {
+            row.into_iter().map(|ir| {
+                if ir.is_empty() {
+                    None
+                } else {
+                    Some(FFTConvolution::with_planner(ir, window_size, Arc::clone(&planner)))
+                }
+            }).collect()
+        }).collect();
+        ConvolutionMatrix {
+            cells,
+            outputs,
+            window_size,
+        }
+    }
+    pub fn inputs(&self) -> usize {
+        self.cells.len()
+    }
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+    pub fn latency(&self) -> usize {
+        self.window_size
+    }
+}
+impl MultiChannelFilter for ConvolutionMatrix {
+    fn clear(&mut self) {
+        for cell in self.cells.iter_mut().flatten().flatten() {
+            cell.clear();
+        }
+    }
+    fn compute(&mut self, input: &[f64], output: &mut [f64]) {
+        assert_eq!(input.len(), self.cells.len(), "input must have one sample per input channel");
+        assert_eq!(output.len(), self.outputs, "output must have one sample per output channel");
+        output.fill(0.0);
+        for (row, signal) in self.cells.iter_mut().zip(input.iter()) {
+            for (cell, out_ref) in row.iter_mut().zip(output.iter_mut()) {
+                if let Some(cell) = cell {
+                    *out_ref += cell.compute(*signal);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::sync::{Arc, Mutex};
+use num_complex::Complex;
+use rustfft::{FftNum, num_traits::{Float, Zero}};
+
+use crate::{ConvolutionError, Filter};
+use crate::dtype::RingBuffer;
+use crate::engine::{Planner, Transform};
+
+/// Uniformly-partitioned overlap-save convolution
+///
+/// The IR is split into `block_size`-sample partitions whose spectra are kept in a
+///  frequency-domain delay line, so the latency is `block_size` no matter how long
+///  the IR is. Each block costs one forward and one inverse FFT of size
+///  `2 * block_size` plus one spectral multiply-add per partition.
+pub struct PartitionedFFTConvolution<T = f64> where T: FftNum + Float {
+    x: RingBuffer<T>,
+    out: RingBuffer<T>,
+    block_size: usize,
+    ir_len: usize,
+    partitions: Vec<Vec<Complex<T>>>,
+    /// Spectra of the most recent input frames, indexed circularly from `fdl_head`
+    fdl: Vec<Vec<Complex<T>>>,
+    fdl_head: usize,
+    previous_block: Vec<T>,
+    transform: Transform<T>,
+    fft_buffer: Vec<T>,
+    accumulator: Vec<Complex<T>>,
+}
+
+impl<T> PartitionedFFTConvolution<T> where T: FftNum + Float {
+    /// Creates the filter, panicking where `try_new` would return an error
+    pub fn new(ir: Vec<T>, block_size: usize) -> PartitionedFFTConvolution<T> {
+        Self::try_new(ir, block_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking that `ir` is not empty, that `block_size` is
+    ///  not 0 and that the FFT size of `2 * block_size` does not overflow
+    pub fn try_new(ir: Vec<T>, block_size: usize) -> Result<PartitionedFFTConvolution<T>, ConvolutionError> {
+        Self::with_planner(ir, block_size, Arc::new(Mutex::new(Planner::new())))
+    }
+    /// Same as `try_new`, but using an existing planner
+    pub fn with_planner(ir: Vec<T>, block_size: usize, planner: Arc<Mutex<Planner<T>>>) -> Result<PartitionedFFTConvolution<T>, ConvolutionError> {
+        let fft_size = checked_fft_size(ir.len(), block_size)?;
+        let mut transform = Transform::new(&planner, fft_size);
+        let spectrum_len = transform.spectrum_len();
+        let partition_count = ir.len().div_ceil(block_size).max(1);
+        let mut fft_buffer = vec![T::zero(); fft_size];
+        let partitions = (0..partition_count).map(|i| {
+            let start = (i * block_size).min(ir.len());
+            let end = ((i + 1) * block_size).min(ir.len());
+            fft_buffer.fill(T::zero());
+            fft_buffer[..end - start].copy_from_slice(&ir[start..end]);
+            let mut spectrum = vec![Complex::zero(); spectrum_len];
+            transform.forward(&mut fft_buffer, &mut spectrum);
+            spectrum
+        }).collect();
+        Ok(PartitionedFFTConvolution {
+            x: RingBuffer::new(block_size),
+            out: RingBuffer::new(block_size).initialize(T::zero()),
+            block_size,
+            ir_len: ir.len(),
+            partitions,
+            fdl: vec![vec![Complex::zero(); spectrum_len]; partition_count],
+            fdl_head: 0,
+            previous_block: vec![T::zero(); block_size],
+            transform,
+            fft_buffer,
+            accumulator: vec![Complex::zero(); spectrum_len],
+        })
+    }
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+    pub fn partition_count(&self) -> usize {
+        self.partitions.len()
+    }
+    pub fn ir_len(&self) -> usize {
+        self.ir_len
+    }
+    /// The delay in samples between a sample entering `compute` and its
+    ///  contribution first appearing in the output
+    pub fn latency(&self) -> usize {
+        self.block_size
+    }
+    fn convolve_block(&mut self) {
+        // Overlap-save frame: the previous block followed by the current one
+        self.fft_buffer[..self.block_size].copy_from_slice(&self.previous_block);
+        for ((buf_ref, prev_ref), x_val) in self.fft_buffer[self.block_size..].iter_mut().zip(self.previous_block.iter_mut()).zip(self.x.inner().iter()) {
+            *buf_ref = *x_val;
+            *prev_ref = *x_val;
+        }
+        self.x.clear();
+
+        let partition_count = self.partitions.len();
+        self.fdl_head = (self.fdl_head + partition_count - 1) % partition_count;
+        self.transform.forward(&mut self.fft_buffer, &mut self.fdl[self.fdl_head]);
+
+        self.accumulator.fill(Complex::zero());
+        for (i, partition) in self.partitions.iter().enumerate() {
+            let frame = &self.fdl[(self.fdl_head + i) % partition_count];
+            for ((acc, x_val), h_val) in self.accumulator.iter_mut().zip(frame.iter()).zip(partition.iter()) {
+                *acc = *acc + *x_val * *h_val;
+            }
+        }
+        self.transform.inverse(&mut self.accumulator, &mut self.fft_buffer);
+
+        // Only the second half of the frame is free of circular wrap-around
+        let scale = T::from_usize(self.transform.len()).unwrap();
+        for (out_ref, buf_val) in self.out.inner_mut().iter_mut().zip(self.fft_buffer[self.block_size..].iter()) {
+            *out_ref = *buf_val / scale;
+        }
+    }
+}
+impl<T> Filter<T> for PartitionedFFTConvolution<T> where T: FftNum + Float {
+    fn clear(&mut self) {
+        self.x.clear();
+        self.out.clear();
+        self.out.initialize_again(T::zero());
+        for frame in self.fdl.iter_mut() {
+            frame.fill(Complex::zero());
+        }
+        self.previous_block.fill(T::zero());
+    }
+    fn compute(&mut self, signal: T) -> T {
+        let buffered_signal = self.out.pop_front().unwrap();
+        self.out.push_back(T::zero());
+
+        self.x.push_back(signal);
+        if self.x.len() == self.block_size {
+            self.convolve_block();
+        }
+
+        buffered_signal
+    }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+    fn flush(&mut self) -> Vec<T> {
+        (0..self.latency() + self.ir_len() - 1).map(|_| self.compute(T::zero())).collect()
+    }
+}
+
+/// The FFT size of `2 * block_size` used by a partitioned convolution
+fn checked_fft_size(ir_len: usize, block_size: usize) -> Result<usize, ConvolutionError> {
+    if ir_len == 0 {
+        return Err(ConvolutionError::EmptyIr);
+    }
+    if block_size == 0 {
+        return Err(ConvolutionError::ZeroWindow);
+    }
+    block_size.checked_mul(2).ok_or(ConvolutionError::Overflow { ir_len, window_size: block_size })
+}
+
+struct Stage<T> where T: FftNum + Float {
+    convolution: PartitionedFFTConvolution<T>,
+    delay: RingBuffer<T>,
+}
+
+/// Non-uniformly partitioned convolution
+///
+/// The IR head is handled with `min_block_size` partitions for low latency, and
+///  every following stage doubles the partition size to cut the number of FFTs
+///  spent on the tail. Stage `k` uses two partitions of `min_block_size * 2^k`
+///  samples, covering the IR from `(2^(k+1) - 2) * min_block_size`, and its output
+///  is delayed so all stages line up with the head. The latency is
+///  `min_block_size`.
+pub struct NonUniformPartitionedConvolution<T = f64> where T: FftNum + Float {
+    stages: Vec<Stage<T>>,
+    min_block_size: usize,
+}
+
+impl<T> NonUniformPartitionedConvolution<T> where T: FftNum + Float {
+    /// Creates the filter, panicking where `try_new` would return an error
+    pub fn new(ir: Vec<T>, min_block_size: usize) -> NonUniformPartitionedConvolution<T> {
+        Self::try_new(ir, min_block_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+    /// Creates the filter, checking that `ir` is not empty, that `min_block_size`
+    ///  is not 0 and that no stage needs an FFT larger than `usize::MAX`
+    pub fn try_new(ir: Vec<T>, min_block_size: usize) -> Result<NonUniformPartitionedConvolution<T>, ConvolutionError> {
+        checked_fft_size(ir.len(), min_block_size)?;
+        let planner = Arc::new(Mutex::new(Planner::new()));
+        let mut stages = Vec::new();
+        let mut block_size = min_block_size;
+        let mut offset: usize = 0;
+        loop {
+            let end = offset.saturating_add(block_size.saturating_mul(2)).min(ir.len());
+            stages.push(Stage {
+                convolution: PartitionedFFTConvolution::with_planner(ir[offset..end].to_vec(), block_size, Arc::clone(&planner))?,
+                delay: RingBuffer::new(offset + min_block_size - block_size).initialize(T::zero()),
+            });
+            offset = end;
+            if offset >= ir.len() {
+                break;
+            }
+            block_size = block_size.checked_mul(2).ok_or(ConvolutionError::Overflow { ir_len: ir.len(), window_size: min_block_size })?;
+        }
+        Ok(NonUniformPartitionedConvolution {
+            stages,
+            min_block_size,
+        })
+    }
+    pub fn min_block_size(&self) -> usize {
+        self.min_block_size
+    }
+    /// The partition size used by each stage, from the IR head to the tail
+    pub fn block_sizes(&self) -> Vec<usize> {
+        self.stages.iter().map(|stage| stage.convolution.block_size()).collect()
+    }
+    /// The length of the whole IR, summed over the stages
+    pub fn ir_len(&self) -> usize {
+        self.stages.iter().map(|stage| stage.convolution.ir_len()).sum()
+    }
+    /// The delay in samples between a sample entering `compute` and its
+    ///  contribution first appearing in the output
+    pub fn latency(&self) -> usize {
+        self.min_block_size
+    }
+}
+impl<T> Filter<T> for NonUniformPartitionedConvolution<T> where T: FftNum + Float {
+    fn clear(&mut self) {
+        for stage in self.stages.iter_mut() {
+            stage.convolution.clear();
+            stage.delay.clear();
+            stage.delay.initialize_again(T::zero());
+        }
+    }
+    fn compute(&mut self, signal: T) -> T {
+        self.stages.iter_mut().fold(T::zero(), |acc, stage| {
+            acc + stage.delay.shift(stage.convolution.compute(signal))
+        })
+    }
+    fn latency(&self) -> usize {
+        self.latency()
+    }
+    fn flush(&mut self) -> Vec<T> {
+        (0..self.latency() + self.ir_len() - 1).map(|_| self.compute(T::zero())).collect()
+    }
+}
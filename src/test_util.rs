@@ -0,0 +1,10 @@
+pub use crate::diagnostics::{naive_convolve, verify_against_naive, white_noise};
+
+/// A unit impulse followed by `len - 1` zeros
+pub fn impulse(len: usize) -> Vec<f64> {
+    let mut signal = vec![0.0; len];
+    if let Some(first) = signal.first_mut() {
+        *first = 1.0;
+    }
+    signal
+}
@@ -0,0 +1,849 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use num_complex::Complex;
+
+use crate::*;
+use crate::diagnostics::{naive_convolve, white_noise};
+
+/// Counts the allocations made on each thread, so that tests running in parallel
+///  don't see each other's
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|count| count.get())
+}
+
+#[test]
+fn compute_does_not_allocate() {
+    let ir: Vec<f64> = (0..300).map(|i| (i as f64 * 0.1).sin()).collect();
+    let mut convolution = FFTConvolution::new(ir, 64);
+    let before = allocations();
+    for i in 0..10_000 {
+        convolution.compute((i as f64 * 0.01).cos());
+    }
+    assert_eq!(allocations() - before, 0);
+}
+
+#[test]
+fn process_block_matches_compute() {
+    let ir = white_noise(500, 1);
+    let input = white_noise(3000, 2);
+    let mut per_sample = FFTConvolution::new(ir.clone(), 128);
+    let expected: Vec<f64> = input.iter().map(|sample| per_sample.compute(*sample)).collect();
+    let mut block = FFTConvolution::new(ir, 128);
+    let mut output = vec![0.0; input.len()];
+    // Uneven blocks, so that runs straddle the chunk boundaries
+    let mut offset = 0;
+    for len in [1, 127, 300, 0, 1000, 1572] {
+        block.process_block(&input[offset..offset + len], &mut output[offset..offset + len]);
+        offset += len;
+    }
+    assert_eq!(offset, input.len());
+    for (expected, actual) in expected.iter().zip(output.iter()) {
+        assert!((expected - actual).abs() <= 1e-12);
+    }
+}
+
+/// Asserts that `output`, delayed by `latency`, is the full linear convolution of
+///  its input with an IR, given as `expected`, for as many samples as it covers
+fn assert_delayed(output: &[f64], expected: &[f64], latency: usize, tolerance: f64) {
+    assert!(output[..latency].iter().all(|sample| sample.abs() <= tolerance), "the latency must be primed with zeros");
+    for (i, (actual, expected)) in output[latency..].iter().zip(expected.iter()).enumerate() {
+        assert!((actual - expected).abs() <= tolerance, "sample {}: expected {}, found {}", i, expected, actual);
+    }
+}
+
+#[test]
+fn partitioned_matches_naive_convolution() {
+    let ir = white_noise(1000, 3);
+    let input = white_noise(4000, 4);
+    let expected = naive_convolve(&input, &ir);
+    let mut convolution = PartitionedFFTConvolution::new(ir, 64);
+    let output: Vec<f64> = input.iter().map(|sample| convolution.compute(*sample)).collect();
+    assert_delayed(&output, &expected, convolution.latency(), 1e-9);
+}
+
+#[test]
+fn partitioned_rejects_zero_block_size() {
+    assert_eq!(PartitionedFFTConvolution::<f64>::try_new(vec![1.0], 0).err(), Some(ConvolutionError::ZeroWindow));
+    assert_eq!(NonUniformPartitionedConvolution::<f64>::try_new(vec![1.0], 0).err(), Some(ConvolutionError::ZeroWindow));
+}
+
+#[test]
+fn partitioned_rejects_an_empty_ir() {
+    assert_eq!(PartitionedFFTConvolution::<f64>::try_new(Vec::new(), 64).err(), Some(ConvolutionError::EmptyIr));
+    assert_eq!(NonUniformPartitionedConvolution::<f64>::try_new(Vec::new(), 64).err(), Some(ConvolutionError::EmptyIr));
+}
+
+#[test]
+fn non_uniform_partitioned_matches_naive_convolution() {
+    let ir = white_noise(5000, 5);
+    let input = white_noise(8000, 6);
+    let expected = naive_convolve(&input, &ir);
+    let mut convolution = NonUniformPartitionedConvolution::new(ir, 32);
+    assert!(convolution.block_sizes().len() > 3);
+    let output: Vec<f64> = input.iter().map(|sample| convolution.compute(*sample)).collect();
+    assert_delayed(&output, &expected, convolution.latency(), 1e-9);
+}
+
+#[test]
+fn fft_convolution_matches_naive_convolution() {
+    let ir = white_noise(700, 7);
+    let input = white_noise(3000, 8);
+    let expected = naive_convolve(&input, &ir);
+    let mut convolution = FFTConvolution::new(ir, 256);
+    let output: Vec<f64> = input.iter().map(|sample| convolution.compute(*sample)).collect();
+    assert_delayed(&output, &expected, convolution.latency(), 1e-9);
+}
+
+/// With the `rayon` feature `process_block` runs the four convolutions in
+///  parallel, and must still be bit-identical to the serial `compute`
+#[test]
+fn true_stereo_block_is_bit_identical_to_compute() {
+    let irs: Vec<Vec<f64>> = (0..4).map(|seed| white_noise(400, 10 + seed)).collect();
+    let new = || TrueStereoFFTConvolution::new(irs[0].clone(), irs[1].clone(), irs[2].clone(), irs[3].clone(), 128);
+    let input: Vec<(f64, f64)> = white_noise(2000, 20).into_iter().zip(white_noise(2000, 21)).collect();
+    let mut serial = new();
+    serial.set_crossfeed(0.7);
+    let expected: Vec<(f64, f64)> = input.iter().map(|signal| serial.compute(*signal)).collect();
+    let mut block = new();
+    block.set_crossfeed(0.7);
+    let mut output = vec![(0.0, 0.0); input.len()];
+    for (input, output) in input.chunks(300).zip(output.chunks_mut(300)) {
+        block.process_block(input, output);
+    }
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn set_ir_swaps_the_response() {
+    let input = white_noise(3000, 30);
+    let (first, second) = (white_noise(300, 31), white_noise(200, 32));
+    let mut convolution = FFTConvolution::new(first.clone(), 128);
+    let mut output: Vec<f64> = input[..1000].iter().map(|sample| convolution.compute(*sample)).collect();
+    // The same padded_window_size, so the spectrum is recomputed in place
+    let fft_size = convolution.fft_size();
+    convolution.set_ir(second.clone());
+    assert_eq!(convolution.fft_size(), fft_size);
+    output.extend(input[1000..].iter().map(|sample| convolution.compute(*sample)));
+    // The 7 chunks convolved before the swap keep the first IR, the samples
+    //  still buffered and everything after use the second
+    let split = 1000 / 128 * 128;
+    let mut expected = naive_convolve(&input[..split], &first);
+    let later = naive_convolve(&input[split..], &second);
+    expected.resize(split + later.len(), 0.0);
+    for (expected, sample) in expected[split..].iter_mut().zip(later) {
+        *expected += sample;
+    }
+    assert_delayed(&output, &expected, convolution.latency(), 1e-9);
+}
+
+#[test]
+fn set_ir_rejects_an_empty_ir() {
+    let mut convolution = FFTConvolution::new(vec![1.0, 0.5], 16);
+    assert_eq!(convolution.try_set_ir(Vec::new()), Err(ConvolutionError::EmptyIr));
+    assert_eq!(convolution.try_set_ir_crossfade(Vec::new(), 4), Err(ConvolutionError::EmptyIr));
+    assert_eq!(convolution.ir(), &[1.0, 0.5]);
+}
+
+#[test]
+#[should_panic(expected = "the impulse response is empty")]
+fn set_ir_panics_on_an_empty_ir() {
+    FFTConvolution::new(vec![1.0], 16).set_ir(Vec::new());
+}
+
+#[test]
+fn set_ir_crossfade_blends_monotonically() {
+    // An impulse on every sample, so each output is the sum of the IR taps
+    let mut convolution = FFTConvolution::new(vec![1.0], 16);
+    for _ in 0..64 {
+        convolution.compute(1.0);
+    }
+    convolution.set_ir_crossfade(vec![0.25], 4);
+    let output: Vec<f64> = (0..256).map(|_| convolution.compute(1.0)).collect();
+    assert!((output[0] - 1.0).abs() < 1e-12);
+    assert!((output[255] - 0.25).abs() < 1e-12);
+    let max_step = 0.75 / (4 * 16) as f64 + 1e-12;
+    for pair in output.windows(2) {
+        assert!(pair[1] <= pair[0] + 1e-12, "the blend must not go back up");
+        assert!(pair[0] - pair[1] <= max_step, "the blend must not step");
+    }
+}
+
+#[test]
+fn flush_returns_the_whole_tail() {
+    let ir = white_noise(500, 40);
+    let mut convolution = FFTConvolution::new(ir.clone(), 128);
+    let mut output = vec![convolution.compute(1.0)];
+    output.extend(convolution.flush());
+    assert_eq!(output.len(), convolution.latency() + ir.len());
+    assert_delayed(&output, &ir, convolution.latency(), 1e-12);
+    // And for a longer signal ending mid-chunk
+    let input = white_noise(1000, 41);
+    let mut output: Vec<f64> = input.iter().map(|sample| convolution.compute(*sample)).collect();
+    output.extend(convolution.flush());
+    let expected = naive_convolve(&input, &ir);
+    assert_eq!(output.len(), convolution.latency() + expected.len());
+    assert_delayed(&output, &expected, convolution.latency(), 1e-9);
+}
+
+#[test]
+fn direct_convolution_matches_fft_convolution() {
+    let ir = white_noise(40, 50);
+    let input = white_noise(1000, 51);
+    let mut direct = DirectConvolution::new(ir.clone());
+    let mut fft = FFTConvolution::new(ir.clone(), 64);
+    let direct_output: Vec<f64> = input.iter().map(|sample| direct.compute(*sample)).collect();
+    let fft_output: Vec<f64> = input.iter().map(|sample| fft.compute(*sample)).collect();
+    assert_delayed(&fft_output, &direct_output, fft.latency(), 1e-12);
+    assert_delayed(&direct_output, &naive_convolve(&input, &ir), 0, 1e-12);
+}
+
+#[test]
+fn auto_convolution_picks_by_ir_length() {
+    // Only the direct path answers an impulse without latency
+    let mut short = auto_convolution(vec![0.5; DIRECT_CONVOLUTION_THRESHOLD - 1], 32);
+    assert_eq!(short.compute(1.0), 0.5);
+    let mut long = auto_convolution(vec![0.5; DIRECT_CONVOLUTION_THRESHOLD], 32);
+    assert_eq!(long.compute(1.0), 0.0);
+}
+
+/// Asserts that two filters give the same output for `input`, sample by sample
+fn assert_same_output<A: Filter, B: Filter>(a: &mut A, b: &mut B, input: &[f64], tolerance: f64) {
+    for (i, sample) in input.iter().enumerate() {
+        let (a_val, b_val) = (a.compute(*sample), b.compute(*sample));
+        assert!((a_val - b_val).abs() <= tolerance, "sample {}: {} != {}", i, a_val, b_val);
+    }
+}
+
+#[test]
+fn overlap_save_matches_overlap_add() {
+    for (ir_len, window_size) in [(1, 16), (100, 16), (300, 128), (1000, 100)] {
+        let ir = white_noise(ir_len, 60);
+        let mut overlap_save = OverlapSaveConvolution::new(ir.clone(), window_size);
+        let mut overlap_add = FFTConvolution::new(ir, window_size);
+        assert_eq!(overlap_save.latency(), overlap_add.latency());
+        assert_same_output(&mut overlap_save, &mut overlap_add, &white_noise(3000, 61), 1e-10);
+    }
+}
+
+#[test]
+fn overlap_save_validates_like_fft_convolution() {
+    assert_eq!(OverlapSaveConvolution::<f64>::try_new(vec![1.0], 0).err(), Some(ConvolutionError::ZeroWindow));
+    assert_eq!(OverlapSaveConvolution::<f64>::try_new(Vec::new(), 16).err(), Some(ConvolutionError::EmptyIr));
+}
+
+#[test]
+fn bypass_delays_the_input_by_the_latency() {
+    let ir = white_noise(300, 5);
+    let input = white_noise(2000, 6);
+    let mut bypassed = FFTConvolution::new(ir.clone(), 64);
+    let mut reference = FFTConvolution::new(ir, 64);
+    bypassed.set_bypass(true);
+    let output: Vec<f64> = input[..1000].iter().map(|sample| bypassed.compute(*sample)).collect();
+    assert_delayed(&output, &input, bypassed.latency(), 0.0);
+    for sample in input[..1000].iter() {
+        reference.compute(*sample);
+    }
+    // The convolution kept running underneath, so switching back is seamless
+    bypassed.set_bypass(false);
+    assert_same_output(&mut bypassed, &mut reference, &input[1000..], 0.0);
+}
+
+#[test]
+fn clear_leaves_only_silence_behind() {
+    let ir = white_noise(300, 7);
+    let input = white_noise(1000, 8);
+    let mut convolution = FFTConvolution::new(ir.clone(), 64);
+    convolution.set_mix(0.5);
+    for sample in input.iter() {
+        convolution.compute(*sample);
+    }
+    convolution.clear();
+    assert_eq!(convolution.output_buffer().len(), convolution.internal_buffer_size());
+    for _ in 0..convolution.internal_buffer_size() {
+        assert_eq!(convolution.compute(0.0), 0.0);
+    }
+    convolution.clear();
+    let mut fresh = FFTConvolution::new(ir, 64);
+    fresh.set_mix(0.5);
+    assert_same_output(&mut convolution, &mut fresh, &input, 0.0);
+}
+
+#[test]
+fn set_gain_smoothed_ramps_without_steps() {
+    let mut convolution = FFTConvolution::new(vec![1.0], 16);
+    for _ in 0..200 {
+        convolution.compute(1.0);
+    }
+    let ramp_len = convolution.internal_buffer_size();
+    convolution.set_gain_smoothed(0.0);
+    let output: Vec<f64> = (0..200).map(|_| convolution.compute(1.0)).collect();
+    assert!((output[0] - 1.0).abs() <= 1e-12);
+    assert!(output[199].abs() <= 1e-12);
+    for pair in output.windows(2) {
+        assert!(pair[1] <= pair[0] + 1e-12, "the ramp must not go back up");
+        assert!(pair[0] - pair[1] <= 1.0 / ramp_len as f64 + 1e-12, "the ramp must not step");
+    }
+    assert!(output.iter().filter(|sample| **sample > 1e-12 && **sample < 1.0 - 1e-12).count() >= ramp_len - 1);
+}
+
+#[test]
+fn convolve_matches_naive_convolution() {
+    for (signal_len, ir_len, window_size) in [(1, 1, 16), (1000, 300, 64), (200, 700, 128), (513, 1, 32)] {
+        let signal = white_noise(signal_len, 9);
+        let ir = white_noise(ir_len, 10);
+        let output = convolve(&signal, &ir, window_size);
+        assert_eq!(output.len(), signal_len + ir_len - 1);
+        assert_delayed(&output, &naive_convolve(&signal, &ir), 0, 1e-9);
+    }
+    assert!(convolve(&[], &[1.0], 16).is_empty());
+    assert!(convolve(&[1.0], &[], 16).is_empty());
+}
+
+#[test]
+fn optimal_window_size_grows_no_faster_than_the_ir() {
+    let mut previous = optimal_window_size(1);
+    for ir_len in 1..5000 {
+        let window_size = optimal_window_size(ir_len);
+        assert!(window_size.is_power_of_two());
+        assert!(window_size <= ir_len.next_power_of_two(), "{} for an IR of {}", window_size, ir_len);
+        assert!(window_size >= previous, "{} for an IR of {}", window_size, ir_len);
+        previous = window_size;
+    }
+}
+
+#[test]
+#[should_panic(expected = "the window size must be set before building")]
+fn builder_requires_a_window_size() {
+    FFTConvolution::<f64>::builder().build(vec![1.0]);
+}
+
+#[test]
+fn compute_with_status_reports_each_block() {
+    let ir = white_noise(100, 11);
+    let input = white_noise(500, 12);
+    let mut with_status = FFTConvolution::new(ir.clone(), 32);
+    let mut reference = FFTConvolution::new(ir, 32);
+    for (i, sample) in input.iter().enumerate() {
+        let (output, convolved) = with_status.compute_with_status(*sample);
+        assert_eq!(convolved, (i + 1) % 32 == 0, "sample {}", i);
+        assert_eq!(convolved, with_status.samples_until_block() == 32);
+        assert_eq!(output, reference.compute(*sample));
+    }
+}
+
+#[test]
+fn flush_denormals_zeroes_a_vanishing_tail() {
+    let ir: Vec<f64> = (0..64).map(|i| if i == 0 { 1.0 } else { 1e-20 }).collect();
+    let mut input = vec![0.0; 256];
+    input[0] = 1.0;
+    let mut flushed = FFTConvolution::new(ir.clone(), 32);
+    flushed.set_flush_denormals(true);
+    let mut kept = FFTConvolution::new(ir, 32);
+    let flushed_output: Vec<f64> = input.iter().map(|sample| flushed.compute(*sample)).collect();
+    let kept_output: Vec<f64> = input.iter().map(|sample| kept.compute(*sample)).collect();
+    assert!((flushed_output[32] - 1.0).abs() <= 1e-12);
+    assert!(flushed_output.iter().all(|sample| *sample == 0.0 || sample.abs() >= DENORMAL_THRESHOLD));
+    assert!(kept_output.iter().any(|sample| *sample != 0.0 && sample.abs() < DENORMAL_THRESHOLD));
+}
+
+#[test]
+fn samples_until_block_counts_down() {
+    let mut convolution = FFTConvolution::new(white_noise(50, 13), 16);
+    let mut countdown = Vec::new();
+    for sample in white_noise(48, 14) {
+        countdown.push(convolution.samples_until_block());
+        convolution.compute(sample);
+    }
+    let expected: Vec<usize> = (0..3).flat_map(|_| (1..=16).rev()).collect();
+    assert_eq!(countdown, expected);
+    convolution.feed(&[0.0; 5]);
+    assert_eq!(convolution.samples_until_block(), 11);
+    convolution.clear();
+    assert_eq!(convolution.samples_until_block(), 16);
+}
+
+#[test]
+fn new_truncated_bounds_the_buffer_size() {
+    let ir = white_noise(10_000, 15);
+    let truncated = FFTConvolution::new_truncated(ir.clone(), 256, 1000);
+    let reference = FFTConvolution::new(ir[..1000].to_vec(), 256);
+    assert_eq!(truncated.ir_len(), 1000);
+    assert_eq!(truncated.internal_buffer_size(), reference.internal_buffer_size());
+    assert!(truncated.internal_buffer_size() < FFTConvolution::new(ir.clone(), 256).internal_buffer_size());
+    // Only the default fade over the last eighth differs from the head of the IR
+    assert_eq!(truncated.ir()[..875], ir[..875]);
+    assert!(truncated.ir()[875..].iter().zip(ir[875..1000].iter()).all(|(faded, original)| faded.abs() <= original.abs()));
+    assert!(truncated.ir()[999].abs() < 1e-3);
+    let faded = FFTConvolution::new_truncated_with_fade(ir.clone(), 256, 1000, 100);
+    assert_eq!(faded.ir()[..900], ir[..900]);
+    assert_ne!(faded.ir()[950], ir[950]);
+    let cut = FFTConvolution::new_truncated_with_fade(ir.clone(), 256, 1000, 0);
+    assert_eq!(cut.ir(), &ir[..1000]);
+    let untouched = FFTConvolution::new_truncated(ir.clone(), 256, 20_000);
+    assert_eq!(untouched.ir(), &ir[..]);
+}
+
+#[test]
+fn zero_width_collapses_to_mono() {
+    let (ir_left, ir_right) = (white_noise(200, 16), white_noise(200, 17));
+    let input: Vec<(f64, f64)> = white_noise(1000, 18).into_iter().zip(white_noise(1000, 19)).collect();
+    let mut narrow = StereoFFTConvolution::new(ir_left.clone(), ir_right.clone(), 64);
+    narrow.set_width(0.0);
+    let mut blocked = StereoFFTConvolution::new(ir_left.clone(), ir_right.clone(), 64);
+    blocked.set_width(0.0);
+    let mut full = StereoFFTConvolution::new(ir_left, ir_right, 64);
+    let mut blocked_output = vec![(0.0, 0.0); input.len()];
+    blocked.process_block(&input, &mut blocked_output);
+    for (sample, blocked_sample) in input.iter().zip(blocked_output.iter()) {
+        let (left, right) = narrow.compute(*sample);
+        let (full_left, full_right) = full.compute(*sample);
+        assert_eq!(left, right);
+        assert!((left - (full_left + full_right) * 0.5).abs() <= 1e-12);
+        assert_eq!(blocked_sample.0, blocked_sample.1);
+        assert!((blocked_sample.0 - left).abs() <= 1e-12);
+    }
+}
+
+#[test]
+fn predelay_shifts_the_response() {
+    let ir = white_noise(100, 20);
+    let mut impulse = vec![0.0; 400];
+    impulse[0] = 1.0;
+    let mut delayed = FFTConvolution::with_predelay(ir.clone(), 37, 32);
+    assert_eq!(delayed.predelay(), 37);
+    let output: Vec<f64> = impulse.iter().map(|sample| delayed.compute(*sample)).collect();
+    assert_delayed(&output, &ir, delayed.latency() + 37, 1e-12);
+    assert!(output[delayed.latency() + 37 + ir.len()..].iter().all(|sample| sample.abs() <= 1e-12));
+}
+
+#[test]
+fn deconvolve_recovers_the_convolved_signal() {
+    let signal = white_noise(700, 21);
+    // A decaying IR has no spectral nulls, so a tiny regularization suffices
+    let ir: Vec<f64> = (0..50).map(|i| 0.5f64.powi(i)).collect();
+    for window_size in [1, 64, 4096] {
+        let output = convolve(&signal, &ir, 64);
+        let recovered = deconvolve(&output, &ir, window_size, 1e-12);
+        assert_eq!(recovered.len(), signal.len());
+        assert_delayed(&recovered, &signal, 0, 1e-9);
+    }
+    assert!(deconvolve(&[1.0], &[1.0, 0.5], 16, 1e-12).is_empty());
+    assert!(deconvolve(&[1.0], &[], 16, 1e-12).is_empty());
+}
+
+#[test]
+fn filter_iter_yields_the_whole_convolution() {
+    let ir = white_noise(300, 22);
+    let input = white_noise(777, 23);
+    let expected = naive_convolve(&input, &ir);
+    let output: Vec<f64> = FFTConvolution::new(ir.clone(), 64).filter_iter(input.iter().copied()).collect();
+    assert_eq!(output.len(), input.len() + ir.len() - 1);
+    assert_delayed(&output, &expected, 0, 1e-9);
+    // The latency and flush are forwarded through a boxed filter
+    let boxed: Box<dyn Filter + Send> = Box::new(FFTConvolution::new(ir.clone(), 64));
+    assert_eq!(boxed.filter_iter(input.iter().copied()).collect::<Vec<f64>>(), output);
+    // A filter without latency or flush yields one sample per input sample
+    let direct: Vec<f64> = DirectConvolution::new(ir).filter_iter(input.iter().copied()).collect();
+    assert_delayed(&direct, &expected[..input.len()], 0, 1e-9);
+    assert_eq!(direct.len(), input.len());
+}
+
+#[test]
+fn filter_iter_flushes_the_tail_of_every_convolution() {
+    let ir = white_noise(300, 25);
+    let input = white_noise(2000, 26);
+    let expected = naive_convolve(&input, &ir);
+    let partitioned: Vec<f64> = PartitionedFFTConvolution::new(ir.clone(), 64).filter_iter(input.iter().copied()).collect();
+    assert_eq!(partitioned.len(), 2299);
+    assert_delayed(&partitioned, &expected, 0, 1e-9);
+    let overlap_save: Vec<f64> = OverlapSaveConvolution::new(ir.clone(), 64).filter_iter(input.iter().copied()).collect();
+    assert_eq!(overlap_save.len(), 2299);
+    assert_delayed(&overlap_save, &expected, 0, 1e-9);
+    let non_uniform: Vec<f64> = NonUniformPartitionedConvolution::new(ir.clone(), 32).filter_iter(input.iter().copied()).collect();
+    assert_eq!(non_uniform.len(), 2299);
+    assert_delayed(&non_uniform, &expected, 0, 1e-9);
+    let layered: Vec<f64> = LayeredConvolution::new(vec![(ir.clone(), 64, 1.0), (ir[..100].to_vec(), 16, 0.0)]).filter_iter(input.iter().copied()).collect();
+    assert_eq!(layered.len(), 2299);
+    assert_delayed(&layered, &expected, 0, 1e-9);
+}
+
+#[test]
+fn filter_iter_primes_a_filter_without_flush() {
+    // Forwards everything but `flush`
+    struct NoFlush(FFTConvolution);
+    impl Filter for NoFlush {
+        fn clear(&mut self) {
+            self.0.clear();
+        }
+        fn compute(&mut self, signal: f64) -> f64 {
+            self.0.compute(signal)
+        }
+        fn latency(&self) -> usize {
+            self.0.latency()
+        }
+    }
+    let ir = white_noise(100, 27);
+    let input = white_noise(500, 28);
+    let expected = naive_convolve(&input, &ir);
+    let output: Vec<f64> = NoFlush(FFTConvolution::new(ir, 64)).filter_iter(input.iter().copied()).collect();
+    assert_eq!(output.len(), input.len());
+    assert_delayed(&output, &expected[..input.len()], 0, 1e-9);
+}
+
+#[test]
+#[cfg(feature="hound")]
+fn convolve_wav_to_wav_writes_the_whole_convolution() {
+    let dir = std::env::temp_dir();
+    let paths: Vec<_> = ["input", "ir", "output", "odd"].iter()
+        .map(|name| dir.join(format!("fft_sound_convolution_{}_{}.wav", name, std::process::id())))
+        .collect();
+    let write = |path: &std::path::Path, channels: u16, samples: &[f64]| {
+        let spec = hound::WavSpec { channels, sample_rate: 48000, bits_per_sample: 32, sample_format: hound::SampleFormat::Float };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for sample in samples {
+            writer.write_sample(*sample as f32).unwrap();
+        }
+        writer.finalize().unwrap();
+    };
+    let input: Vec<f64> = white_noise(1000, 78).iter().map(|sample| sample * 0.5).collect();
+    let ir: Vec<f64> = white_noise(300, 79).iter().map(|sample| sample * 0.1).collect();
+    write(&paths[0], 1, &input);
+    write(&paths[1], 1, &ir);
+    // hound won't write half a frame, so drop the last sample afterwards to get
+    //  a stereo input with an odd number of samples
+    write(&paths[3], 2, &input);
+    let mut bytes = std::fs::read(&paths[3]).unwrap();
+    let data = bytes.windows(4).position(|id| id == b"data").unwrap();
+    let data_len = u32::from_le_bytes(bytes[data + 4..data + 8].try_into().unwrap()) - 4;
+    bytes[data + 4..data + 8].copy_from_slice(&data_len.to_le_bytes());
+    bytes.truncate(bytes.len() - 4);
+    let riff_len = bytes.len() as u32 - 8;
+    bytes[4..8].copy_from_slice(&riff_len.to_le_bytes());
+    std::fs::write(&paths[3], bytes).unwrap();
+    convolve_wav_to_wav(&paths[0], &paths[1], &paths[2], 64).unwrap();
+    let output = wav::load_ir_wav(&paths[2]).unwrap();
+    let odd = convolve_wav_to_wav(&paths[3], &paths[1], &paths[2], 64);
+    for path in paths.iter() {
+        std::fs::remove_file(path).unwrap();
+    }
+    // Both files went through f32
+    let input: Vec<f64> = input.iter().map(|sample| *sample as f32 as f64).collect();
+    let ir: Vec<f64> = ir.iter().map(|sample| *sample as f32 as f64).collect();
+    assert_eq!(output.len(), input.len() + ir.len() - 1);
+    assert_delayed(&output, &naive_convolve(&input, &ir), 0, 1e-5);
+    assert!(matches!(odd, Err(ConvolutionError::File(_))), "{:?}", odd);
+}
+
+#[test]
+#[cfg(feature="hound")]
+fn wav_loading_reports_an_unsupported_format() {
+    let path = std::env::temp_dir().join(format!("fft_sound_convolution_unsupported_{}.wav", std::process::id()));
+    let spec = hound::WavSpec { channels: 1, sample_rate: 48000, bits_per_sample: 24, sample_format: hound::SampleFormat::Int };
+    let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+    writer.write_sample(1i32).unwrap();
+    writer.finalize().unwrap();
+    // hound only writes whole bytes, so mark 20 of the 24 bits as valid in the
+    //  WAVEFORMATEXTENSIBLE header it wrote
+    let mut bytes = std::fs::read(&path).unwrap();
+    assert_eq!(&bytes[12..20], b"fmt \x28\0\0\0");
+    bytes[38..40].copy_from_slice(&20u16.to_le_bytes());
+    std::fs::write(&path, bytes).unwrap();
+    let result = wav::load_wav_channels(&path);
+    std::fs::remove_file(&path).unwrap();
+    match result {
+        Err(IrLoadError::Convolution(err)) => assert_eq!(err, ConvolutionError::UnsupportedFormat { bits_per_sample: 20, float: false }),
+        other => panic!("expected an unsupported format, found {:?}", other),
+    }
+}
+
+#[test]
+fn process_inplace_matches_process_block() {
+    let ir = white_noise(400, 24);
+    let input = white_noise(3000, 25);
+    let mut separate = FFTConvolution::new(ir.clone(), 128);
+    let mut expected = vec![0.0; input.len()];
+    separate.process_block(&input, &mut expected);
+    let mut inplace = FFTConvolution::new(ir, 128);
+    let mut buffer = input.clone();
+    let mut offset = 0;
+    for len in [5, 123, 0, 128, 1000, 1744] {
+        inplace.process_inplace(&mut buffer[offset..offset + len]);
+        offset += len;
+    }
+    assert_eq!(offset, input.len());
+    assert_eq!(buffer, expected);
+}
+
+#[test]
+fn freeze_sustains_the_tail() {
+    let ir: Vec<f64> = white_noise(200, 26).iter().enumerate().map(|(i, sample)| sample * 0.98f64.powi(i as i32)).collect();
+    let input = white_noise(640, 27);
+    let mut frozen = FFTConvolution::new(ir.clone(), 64);
+    let mut released = FFTConvolution::new(ir, 64);
+    frozen.feed(&input);
+    released.feed(&input);
+    frozen.set_freeze(true);
+    // The input is ignored while frozen
+    let sustained = frozen.feed(&white_noise(1024, 28));
+    let decayed = released.feed(&[0.0; 1024]);
+    let settled = &sustained[512..];
+    for (i, pair) in settled.iter().zip(settled[64..].iter()).enumerate() {
+        assert!((pair.0 - pair.1).abs() <= 1e-12, "sample {} is not periodic", i);
+    }
+    assert!(settled.iter().map(|sample| sample * sample).sum::<f64>() > 1e-3);
+    assert!(decayed[512..].iter().all(|sample| sample.abs() <= 1e-12));
+}
+
+#[test]
+fn output_clip_trips_the_flag() {
+    let mut convolution = FFTConvolution::new(vec![2.0], 16);
+    convolution.set_output_clip(true);
+    convolution.feed(&[0.25; 32]);
+    assert!(!convolution.had_clipping());
+    let output = convolution.feed(&[1.0; 32]);
+    assert!(output.iter().all(|sample| sample.abs() <= 1.0));
+    assert_eq!(output[16..], [1.0; 16]);
+    assert!(convolution.had_clipping());
+    // The flag stays up until it is reset
+    assert!(convolution.had_clipping());
+    convolution.reset_clipping();
+    assert!(!convolution.had_clipping());
+    convolution.set_output_clip(false);
+    assert!(convolution.feed(&[0.0; 16]).iter().all(|sample| (sample - 2.0).abs() <= 1e-12));
+    assert!(!convolution.had_clipping());
+}
+
+#[test]
+fn minimum_phase_keeps_the_magnitude() {
+    // Zeros outside the unit circle, so the IR is far from minimum phase
+    let ir = [[1.0, -2.0], [1.0, 0.5], [1.0, -1.5], [0.3, 1.0]].iter().fold(vec![1.0], |ir, factor| naive_convolve(&ir, factor));
+    let minimum = to_minimum_phase(&ir);
+    assert_eq!(minimum.len(), ir.len());
+    let expected = FFTConvolution::new(ir.clone(), 64).ir_magnitude_response();
+    let actual = FFTConvolution::new(minimum.clone(), 64).ir_magnitude_response();
+    for (i, (expected, actual)) in expected.iter().zip(actual.iter()).enumerate() {
+        assert!((expected - actual).abs() <= 1e-9 * expected.max(1.0), "bin {}: {} != {}", i, expected, actual);
+    }
+    // Every prefix holds at least as much energy as that of the original
+    let energy = |ir: &[f64], len: usize| ir[..len].iter().map(|sample| sample * sample).sum::<f64>();
+    for len in 1..=ir.len() {
+        assert!(energy(&minimum, len) >= energy(&ir, len) - 1e-9);
+    }
+    assert!(to_minimum_phase(&[]).is_empty());
+}
+
+#[test]
+fn compute_split_pairs_the_delayed_dry_with_the_wet() {
+    let ir = white_noise(300, 29);
+    let input = white_noise(1000, 30);
+    let mut split = FFTConvolution::new(ir.clone(), 64);
+    split.set_mix(0.3);
+    let mut wet_only = FFTConvolution::new(ir, 64);
+    let (dry, wet): (Vec<f64>, Vec<f64>) = input.iter().map(|sample| split.compute_split(*sample)).unzip();
+    assert_delayed(&dry, &input, split.latency(), 0.0);
+    let expected: Vec<f64> = input.iter().map(|sample| wet_only.compute(*sample)).collect();
+    assert_eq!(wet, expected);
+}
+
+#[test]
+fn envelope_of_a_sine_converges_to_its_amplitude() {
+    let mut follower = EnvelopeFollower::new(10, 1000);
+    let envelope: Vec<f64> = (0..4000).map(|i| follower.compute(0.5 * (i as f64 * 0.2 * std::f64::consts::PI).sin())).collect();
+    assert!(envelope[..follower.latency()].iter().all(|sample| *sample < 0.5));
+    for sample in envelope[2000..].iter() {
+        assert!((sample - 0.5).abs() <= 0.01, "{}", sample);
+    }
+    assert_eq!(follower.envelope(), envelope[3999]);
+    follower.clear();
+    assert_eq!(follower.envelope(), 0.0);
+}
+
+#[test]
+fn feed_accepts_buffers_of_any_length() {
+    let ir = white_noise(5000, 31);
+    let input = white_noise(6000, 32);
+    let mut fed = FFTConvolution::new(ir.clone(), 4096);
+    let mut output = Vec::new();
+    let mut offset = 0;
+    for len in [100, 300, 1, 0, 777, 128, 4096, 598] {
+        let chunk = fed.feed(&input[offset..offset + len]);
+        assert_eq!(chunk.len(), len);
+        output.extend(chunk);
+        offset += len;
+    }
+    assert_eq!(offset, input.len());
+    assert_delayed(&output, &naive_convolve(&input, &ir), fed.latency(), 1e-9);
+}
+
+#[test]
+fn spectral_gate_attenuates_the_noise_floor() {
+    let window_size = 256;
+    let tone: Vec<f64> = (0..4096).map(|i| 0.5 * (2.0 * std::f64::consts::PI * 16.0 * i as f64 / window_size as f64).sin()).collect();
+    let noisy: Vec<f64> = tone.iter().zip(white_noise(4096, 33)).map(|(tone, noise)| tone + noise * 1e-3).collect();
+    let residual_energy = |gate: Option<f64>| {
+        let mut convolution = FFTConvolution::new(vec![1.0], window_size);
+        if let Some(threshold_db) = gate {
+            convolution.set_spectral_gate(threshold_db);
+        }
+        let output = convolution.feed(&noisy);
+        output[window_size..].iter().zip(tone.iter()).map(|(output, tone)| (output - tone).powi(2)).sum::<f64>()
+    };
+    let (open, gated) = (residual_energy(None), residual_energy(Some(-40.0)));
+    assert!(gated < open * 0.05, "gated {} against {}", gated, open);
+}
+
+#[test]
+fn verify_against_naive_reports_a_small_error() {
+    for (ir_len, window_size) in [(1, 16), (64, 64), (1000, 128), (5000, 1024)] {
+        let error = verify_against_naive(&white_noise(ir_len, 34), window_size, 4000, 35);
+        assert!(error > 0.0 || ir_len == 1);
+        assert!(error < 1e-10, "{} for an IR of {} with a window of {}", error, ir_len, window_size);
+    }
+}
+
+#[test]
+#[should_panic(expected = "verify_against_naive needs a non-empty IR")]
+fn verify_against_naive_panics_on_an_empty_ir() {
+    verify_against_naive(&[], 16, 100, 0);
+}
+
+#[test]
+fn mid_side_with_equal_irs_is_dual_mono() {
+    let ir = white_noise(300, 36);
+    let input: Vec<(f64, f64)> = white_noise(1500, 37).into_iter().zip(white_noise(1500, 38)).collect();
+    let mut mid_side = MidSideFFTConvolution::new(ir.clone(), ir.clone(), 64);
+    let mut stereo = StereoFFTConvolution::new(ir.clone(), ir, 64);
+    assert_eq!(mid_side.latency(), stereo.latency());
+    for (i, sample) in input.iter().enumerate() {
+        let (left, right) = mid_side.compute(*sample);
+        let (expected_left, expected_right) = stereo.compute(*sample);
+        assert!((left - expected_left).abs() <= 1e-12 && (right - expected_right).abs() <= 1e-12, "sample {}", i);
+    }
+}
+
+#[test]
+fn layered_sums_the_aligned_layers() {
+    let layers = vec![(white_noise(100, 39), 32, 0.5), (white_noise(700, 40), 256, -1.2), (white_noise(10, 41), 16, 2.0)];
+    let mut expected = vec![0.0; 700];
+    for (ir, _, gain) in layers.iter() {
+        for (expected, sample) in expected.iter_mut().zip(ir.iter()) {
+            *expected += sample * gain;
+        }
+    }
+    let mut layered = LayeredConvolution::new(layers);
+    assert_eq!(layered.latency(), 256);
+    let mut impulse = vec![0.0; 1200];
+    impulse[0] = 1.0;
+    let output: Vec<f64> = impulse.iter().map(|sample| layered.compute(*sample)).collect();
+    assert_delayed(&output, &expected, layered.latency(), 1e-12);
+    assert!(output[layered.latency() + expected.len()..].iter().all(|sample| sample.abs() <= 1e-12));
+}
+
+#[test]
+fn set_window_size_rejects_zero() {
+    let mut convolution = FFTConvolution::new(white_noise(100, 42), 32);
+    assert_eq!(convolution.try_set_window_size(0), Err(ConvolutionError::ZeroWindow));
+    assert_eq!(convolution.window_size(), 32);
+    assert_eq!(convolution.try_set_window_size(64), Ok(()));
+    assert_eq!(convolution.latency(), 64);
+}
+
+#[test]
+#[should_panic(expected = "the window size must be greater than 0")]
+fn set_window_size_panics_on_zero() {
+    FFTConvolution::new(vec![1.0], 32).set_window_size(0);
+}
+
+#[test]
+#[should_panic(expected = "the impulse response is empty")]
+fn with_planner_panics_on_an_empty_ir() {
+    FFTConvolution::<f64>::with_planner(Vec::new(), 32, std::sync::Arc::new(std::sync::Mutex::new(Planner::new())));
+}
+
+#[test]
+#[should_panic(expected = "the window size must be greater than 0")]
+fn multichannel_panics_on_a_zero_window() {
+    MultiChannelFFTConvolution::new(vec![vec![1.0], vec![0.5]], 0);
+}
+
+#[test]
+fn zero_latency_matches_naive_convolution() {
+    for (ir_len, window_size) in [(10, 32), (32, 32), (500, 64)] {
+        let ir = white_noise(ir_len, 43);
+        let input = white_noise(2000, 44);
+        let mut zero_latency = FFTConvolution::new_zero_latency(ir.clone(), window_size);
+        assert_eq!(zero_latency.latency(), 0);
+        let output: Vec<f64> = input.iter().map(|sample| zero_latency.compute(*sample)).collect();
+        assert_delayed(&output, &naive_convolve(&input, &ir), 0, 1e-9);
+        // With a constant gain it is `new` without the latency
+        let mut delayed = FFTConvolution::new(ir.clone(), window_size);
+        delayed.set_gain(0.7);
+        zero_latency.clear();
+        zero_latency.set_gain(0.7);
+        let expected: Vec<f64> = input.iter().map(|sample| delayed.compute(*sample)).collect();
+        let output: Vec<f64> = input.iter().map(|sample| zero_latency.compute(*sample)).collect();
+        assert_delayed(&expected, &output, window_size, 1e-12);
+    }
+}
+
+#[test]
+fn complex_convolution_matches_real_convolution_on_real_input() {
+    let ir = white_noise(200, 70);
+    let input = white_noise(1000, 71);
+    let real: Vec<f64> = FFTConvolution::new(ir.clone(), 64).filter_iter(input.iter().copied()).collect();
+    let complex_ir = ir.iter().map(|&re| Complex::new(re, 0.0)).collect();
+    let complex: Vec<Complex<f64>> = ComplexFFTConvolution::new(complex_ir, 64).filter_iter(input.iter().map(|&re| Complex::new(re, 0.0))).collect();
+    assert_eq!(complex.len(), real.len());
+    for (i, (complex_val, real_val)) in complex.iter().zip(real.iter()).enumerate() {
+        assert!((complex_val - real_val).norm() <= 1e-9, "sample {}: {} != {}", i, complex_val, real_val);
+    }
+}
+
+#[test]
+fn from_spectrum_and_from_shared_match_new() {
+    let ir = white_noise(300, 72);
+    let input = white_noise(2000, 73);
+    let original = FFTConvolution::new(ir.clone(), 128);
+    let mut from_spectrum = FFTConvolution::from_spectrum(original.ir_spectrum().to_vec(), ir.len(), 128).unwrap();
+    assert_same_output(&mut FFTConvolution::new(ir.clone(), 128), &mut from_spectrum, &input, 1e-10);
+    let mut from_shared = FFTConvolution::from_shared(original.shared_ir_spectrum(), ir.len(), 128, original.fft_planner()).unwrap();
+    assert_same_output(&mut FFTConvolution::new(ir, 128), &mut from_shared, &input, 1e-10);
+}
+
+#[test]
+fn stereo_process_interleaved_matches_compute() {
+    let (ir_left, ir_right) = (white_noise(300, 74), white_noise(200, 75));
+    let (left, right) = (white_noise(1000, 76), white_noise(1000, 77));
+    let mut framewise = StereoFFTConvolution::new(ir_left.clone(), ir_right.clone(), 64);
+    let expected: Vec<f64> = left.iter().zip(right.iter()).flat_map(|(&l, &r)| {
+        let (l, r) = framewise.compute((l, r));
+        [l, r]
+    }).collect();
+    let mut buffer: Vec<f64> = left.iter().zip(right.iter()).flat_map(|(&l, &r)| [l, r]).collect();
+    StereoFFTConvolution::new(ir_left, ir_right, 64).process_interleaved(&mut buffer);
+    assert_eq!(buffer, expected);
+}
@@ -0,0 +1,257 @@
+use std::{fmt, io, path::Path};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+use crate::{ConvolutionError, FFTConvolution, StereoFFTConvolution, StereoFilter, TrueStereoFFTConvolution, resample_ir};
+
+/// Errors that can occur while loading an impulse response from a WAV file
+#[derive(Debug)]
+pub enum IrLoadError {
+    /// The file could not be opened or decoded
+    Wav(hound::Error),
+    /// The file does not have the number of channels the loader expects
+    ChannelCount { expected: u16, found: u16 },
+    /// The impulse responses cannot be convolved with, for instance because the
+    ///  file has no samples or they are stored in an unsupported format
+    Convolution(ConvolutionError),
+}
+impl fmt::Display for IrLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrLoadError::Wav(err) => write!(f, "failed to read WAV file: {}", err),
+            IrLoadError::ChannelCount { expected, found } => {
+                write!(f, "expected a WAV file with {} channel(s), found {}", expected, found)
+            },
+            IrLoadError::Convolution(err) => write!(f, "{}", err),
+        }
+    }
+}
+impl std::error::Error for IrLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            IrLoadError::Wav(err) => Some(err),
+            IrLoadError::Convolution(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+impl From<hound::Error> for IrLoadError {
+    fn from(value: hound::Error) -> Self {
+        IrLoadError::Wav(value)
+    }
+}
+impl From<ConvolutionError> for IrLoadError {
+    fn from(value: ConvolutionError) -> Self {
+        IrLoadError::Convolution(value)
+    }
+}
+impl From<IrLoadError> for ConvolutionError {
+    fn from(value: IrLoadError) -> Self {
+        match value {
+            IrLoadError::Convolution(err) => err,
+            err => ConvolutionError::File(err.to_string()),
+        }
+    }
+}
+impl From<hound::Error> for ConvolutionError {
+    fn from(value: hound::Error) -> Self {
+        IrLoadError::Wav(value).into()
+    }
+}
+
+/// Reads every channel of a WAV file, converting the samples to `f64` in
+///  `-1.0..1.0`
+///
+/// Integer PCM of 8, 16, 24 or 32 bits is scaled by `1 / 2^(bits - 1)`, and 32-bit
+///  float samples are used as is.
+pub fn load_wav_channels<P: AsRef<Path>>(path: P) -> Result<Vec<Vec<f64>>, IrLoadError> {
+    Ok(load_wav_channels_with_rate(path)?.0)
+}
+
+/// Same as `load_wav_channels`, but also returns the sample rate of the file in Hz
+pub fn load_wav_channels_with_rate<P: AsRef<Path>>(path: P) -> Result<(Vec<Vec<f64>>, u32), IrLoadError> {
+    let reader = WavReader::open(path)?;
+    let spec = reader.spec();
+    let samples: Vec<f64> = read_samples(reader)?.collect::<Result<_, _>>()?;
+    let channels = spec.channels as usize;
+    Ok(((0..channels).map(|channel| samples.iter().skip(channel).step_by(channels).copied().collect()).collect(), spec.sample_rate))
+}
+
+/// The interleaved samples of a WAV file as `f64`, scaled as described for
+///  `load_wav_channels`
+fn read_samples<R: io::Read + 'static>(reader: WavReader<R>) -> Result<Box<dyn Iterator<Item = Result<f64, hound::Error>>>, IrLoadError> {
+    let spec = reader.spec();
+    match (spec.sample_format, spec.bits_per_sample) {
+        (SampleFormat::Int, bits @ (8 | 16 | 24 | 32)) => {
+            let scale = 1.0 / (1u64 << (bits - 1)) as f64;
+            Ok(Box::new(reader.into_samples::<i32>().map(move |sample| sample.map(|sample| sample as f64 * scale))))
+        },
+        (SampleFormat::Float, 32) => {
+            Ok(Box::new(reader.into_samples::<f32>().map(|sample| sample.map(|sample| sample as f64))))
+        },
+        (sample_format, bits_per_sample) => Err(IrLoadError::Convolution(ConvolutionError::UnsupportedFormat {
+            bits_per_sample,
+            float: sample_format == SampleFormat::Float,
+        })),
+    }
+}
+
+fn load_wav_with_channels<P: AsRef<Path>>(path: P, expected: u16) -> Result<Vec<Vec<f64>>, IrLoadError> {
+    let channels = load_wav_channels(path)?;
+    if channels.len() != expected as usize {
+        return Err(IrLoadError::ChannelCount { expected, found: channels.len() as u16 });
+    }
+    Ok(channels)
+}
+
+/// Loads a mono impulse response
+pub fn load_ir_wav<P: AsRef<Path>>(path: P) -> Result<Vec<f64>, IrLoadError> {
+    let mut channels = load_wav_with_channels(path, 1)?;
+    Ok(channels.remove(0))
+}
+
+/// Loads a mono impulse response and resamples it to `target_hz` with
+///  `resample_ir` if it was recorded at another rate
+pub fn load_ir_wav_resampled<P: AsRef<Path>>(path: P, target_hz: u32) -> Result<Vec<f64>, IrLoadError> {
+    let (mut channels, sample_rate) = load_wav_channels_with_rate(path)?;
+    if channels.len() != 1 {
+        return Err(IrLoadError::ChannelCount { expected: 1, found: channels.len() as u16 });
+    }
+    Ok(resample_ir(&channels.remove(0), sample_rate, target_hz))
+}
+
+/// Loads a stereo impulse response as `(left, right)`
+pub fn load_stereo_ir_wav<P: AsRef<Path>>(path: P) -> Result<(Vec<f64>, Vec<f64>), IrLoadError> {
+    let mut channels = load_wav_with_channels(path, 2)?.into_iter();
+    Ok((channels.next().unwrap(), channels.next().unwrap()))
+}
+
+impl TrueStereoFFTConvolution {
+    /// Creates a true-stereo convolution from a 4-channel WAV file
+    ///
+    /// The channels are read in the order LL, LR, RL, RR, where LR is the response
+    ///  of the right output to the left input and RL the response of the left
+    ///  output to the right input.
+    pub fn from_wav<P: AsRef<Path>>(path: P, window_size: usize) -> Result<TrueStereoFFTConvolution, IrLoadError> {
+        let mut channels = load_wav_with_channels(path, 4)?.into_iter();
+        let (ir_ll, ir_lr, ir_rl, ir_rr) = (channels.next().unwrap(), channels.next().unwrap(), channels.next().unwrap(), channels.next().unwrap());
+        Ok(TrueStereoFFTConvolution::try_new(ir_ll, ir_rr, ir_lr, ir_rl, window_size)?)
+    }
+}
+
+impl StereoFFTConvolution {
+    /// Creates a stereo convolution from a 2-channel WAV file, channel 0 being the
+    ///  left IR and channel 1 the right one
+    ///
+    /// A mono file is accepted as well and used for both channels, as with
+    ///  `mono_ir`.
+    pub fn from_stereo_wav<P: AsRef<Path>>(path: P, window_size: usize) -> Result<StereoFFTConvolution, IrLoadError> {
+        let mut channels = load_wav_channels(path)?;
+        match channels.len() {
+            1 => {
+                let ir = channels.remove(0);
+                FFTConvolution::<f64>::checked_padded_window_size(ir.len(), window_size)?;
+                Ok(StereoFFTConvolution::mono_ir(ir, window_size))
+            },
+            2 => {
+                let ir_right = channels.pop().unwrap();
+                let ir_left = channels.pop().unwrap();
+                Ok(StereoFFTConvolution::try_new(ir_left, ir_right, window_size)?)
+            },
+            found => Err(IrLoadError::ChannelCount { expected: 2, found: found as u16 }),
+        }
+    }
+}
+
+/// Convolves a whole WAV file with the IR in another WAV file, writing the result
+///  to `output`
+///
+/// The input is streamed through in blocks of `window_size` frames rather than
+///  loaded at once. A mono input needs a mono IR and is run through an
+///  `FFTConvolution`, a stereo input a mono or stereo IR and is run through a
+///  `StereoFFTConvolution`. The output has the channel count and sample rate of
+///  the input, is written as 32-bit float so that nothing is clipped, and holds
+///  the full convolution, `input_len + ir_len - 1` frames with the latency
+///  trimmed off.
+///
+/// A failure to read or write either file is returned as
+///  `ConvolutionError::File`, as is a stereo input with an odd number of
+///  samples, which hound already rejects when opening it.
+pub fn convolve_wav_to_wav(input: &Path, ir: &Path, output: &Path, window_size: usize) -> Result<(), ConvolutionError> {
+    let reader = WavReader::open(input)?;
+    let spec = reader.spec();
+    let output_spec = WavSpec {
+        channels: spec.channels,
+        sample_rate: spec.sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut samples = read_samples(reader)?;
+    match spec.channels {
+        1 => {
+            let mut convolution = FFTConvolution::try_new(load_ir_wav(ir)?, window_size)?;
+            let mut writer = WavWriter::create(output, output_spec)?;
+            let mut skip = convolution.latency();
+            let mut block = Vec::with_capacity(window_size);
+            let mut block_output = vec![0.0; window_size];
+            loop {
+                block.clear();
+                for sample in samples.by_ref().take(window_size) {
+                    block.push(sample?);
+                }
+                if block.is_empty() {
+                    break;
+                }
+                convolution.process_block(&block, &mut block_output[..block.len()]);
+                for sample in block_output[..block.len()].iter() {
+                    if skip > 0 {
+                        skip -= 1;
+                    } else {
+                        writer.write_sample(*sample as f32)?;
+                    }
+                }
+            }
+            for sample in convolution.flush().into_iter().skip(skip) {
+                writer.write_sample(sample as f32)?;
+            }
+            writer.finalize()?;
+        },
+        2 => {
+            let mut convolution = StereoFFTConvolution::from_stereo_wav(ir, window_size)?;
+            let mut writer = WavWriter::create(output, output_spec)?;
+            let mut skip = convolution.latency();
+            let mut block = Vec::with_capacity(window_size);
+            let mut block_output = vec![(0.0, 0.0); window_size];
+            loop {
+                block.clear();
+                while block.len() < window_size {
+                    match (samples.next(), samples.next()) {
+                        (Some(left), Some(right)) => block.push((left?, right?)),
+                        (Some(_), None) => {
+                            return Err(ConvolutionError::File("the stereo input ends halfway through a frame".to_string()));
+                        },
+                        (None, _) => break,
+                    }
+                }
+                if block.is_empty() {
+                    break;
+                }
+                convolution.process_block(&block, &mut block_output[..block.len()]);
+                for (left, right) in block_output[..block.len()].iter() {
+                    if skip > 0 {
+                        skip -= 1;
+                    } else {
+                        writer.write_sample(*left as f32)?;
+                        writer.write_sample(*right as f32)?;
+                    }
+                }
+            }
+            for (left, right) in convolution.flush().into_iter().skip(skip) {
+                writer.write_sample(left as f32)?;
+                writer.write_sample(right as f32)?;
+            }
+            writer.finalize()?;
+        },
+        found => return Err(IrLoadError::ChannelCount { expected: 2, found }.into()),
+    }
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::f64::consts::PI;
+
+/// Evaluates a generalized cosine window `sum_i (-1)^i * a_i * cos(2 * pi * i * k / (n - 1))`
+fn cosine_window(n: usize, coefficients: &[f64]) -> Vec<f64> {
+    if n == 1 {
+        return vec![1.0];
+    }
+    (0..n).map(|k| {
+        let phase = 2.0 * PI * k as f64 / (n - 1) as f64;
+        coefficients.iter().enumerate().fold(0.0, |acc, (i, a)| {
+            let term = a * (phase * i as f64).cos();
+            if i % 2 == 0 { acc + term } else { acc - term }
+        })
+    }).collect()
+}
+
+/// `0.5 - 0.5 * cos(2 * pi * k / (n - 1))`
+pub fn hann(n: usize) -> Vec<f64> {
+    cosine_window(n, &[0.5, 0.5])
+}
+
+/// `0.54 - 0.46 * cos(2 * pi * k / (n - 1))`
+pub fn hamming(n: usize) -> Vec<f64> {
+    cosine_window(n, &[0.54, 0.46])
+}
+
+/// `0.42 - 0.5 * cos(2 * pi * k / (n - 1)) + 0.08 * cos(4 * pi * k / (n - 1))`
+pub fn blackman(n: usize) -> Vec<f64> {
+    cosine_window(n, &[0.42, 0.5, 0.08])
+}
+
+/// All ones
+pub fn rectangular(n: usize) -> Vec<f64> {
+    vec![1.0; n]
+}
+
+/// Multiplies `frame` by `window` in place
+pub fn apply_window(frame: &mut [f64], window: &[f64]) {
+    assert_eq!(frame.len(), window.len(), "frame and window must be the same length");
+    for (sample, w) in frame.iter_mut().zip(window.iter()) {
+        *sample *= *w;
+    }
+}